@@ -0,0 +1,22 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use merkle_cbt::blake2b::{blake2b_256, CBMT};
+
+fn bench_build_merkle_tree(c: &mut Criterion) {
+    let mut group = c.benchmark_group("build_merkle_tree");
+    group.sample_size(20);
+    for len in [1_000u32, 10_000, 50_000] {
+        let leaves = (0..len)
+            .map(|i| blake2b_256(&i.to_le_bytes()))
+            .collect::<Vec<_>>();
+        group.bench_with_input(BenchmarkId::new("sequential", len), &leaves, |b, leaves| {
+            b.iter(|| CBMT::build_merkle_tree(leaves))
+        });
+        group.bench_with_input(BenchmarkId::new("rayon", len), &leaves, |b, leaves| {
+            b.iter(|| CBMT::build_merkle_tree_par(leaves))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_build_merkle_tree);
+criterion_main!(benches);
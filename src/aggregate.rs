@@ -0,0 +1,358 @@
+use crate::collections::{BTreeMap, BinaryHeap};
+use crate::error::MerkleError;
+use crate::merkle_tree::{collect_lemmas, Merge, MerkleProof, TreeIndex};
+use crate::proof_ref::visit_nodes;
+use crate::{vec, vec::Vec};
+use core::cmp::Reverse;
+
+impl<T, M> MerkleProof<T, M>
+where
+    T: Ord + Default + Clone,
+    M: Merge<Item = T>,
+{
+    /// Combines two proofs of the same tree into a minimal multi-proof of the
+    /// leaves of both, lemmas that can be calculated from the other proof are
+    /// dropped. Leaves proved by both proofs are proved once, and an index which
+    /// is an ancestor of another one, e.g. an interior node presented as a leaf,
+    /// is rejected.
+    ///
+    /// `leaves` and `other_leaves`: the leaves of `self` and `other`, as passed to
+    /// `root`
+    pub fn merge(&self, other: &Self, leaves: &[T], other_leaves: &[T]) -> Option<Self> {
+        self.try_merge(other, leaves, other_leaves).ok()
+    }
+
+    /// Same as `merge`, but returns the reason of the failure.
+    pub fn try_merge(
+        &self,
+        other: &Self,
+        leaves: &[T],
+        other_leaves: &[T],
+    ) -> Result<Self, MerkleError> {
+        let mut nodes = self.try_compute_nodes(leaves)?;
+        for (index, node) in other.try_compute_nodes(other_leaves)? {
+            match nodes.get(&index) {
+                Some(existing) if existing != &node => {
+                    return Err(MerkleError::NodeMismatch { index })
+                }
+                Some(_) => {}
+                None => {
+                    nodes.insert(index, node);
+                }
+            }
+        }
+
+        let mut indices = self
+            .indices
+            .iter()
+            .chain(other.indices.iter())
+            .copied()
+            .collect::<Vec<_>>();
+        indices.sort_by_key(|i| Reverse(*i));
+        indices.dedup();
+
+        // nodes are merged from the greatest index, the order `calculate_root`
+        // takes the lemmas, each is paired with the index it is merged from
+        let mut queue = indices
+            .iter()
+            .map(|index| (*index, *index))
+            .collect::<BinaryHeap<_>>();
+        let mut lemmas = Vec::new();
+        while let Some((index, descendant)) = pop_node(&mut queue)? {
+            if index == 0 {
+                break;
+            }
+            let sibling = index.sibling();
+            if queue.peek().map(|(next, _)| *next) == Some(sibling) {
+                pop_node(&mut queue)?;
+            } else {
+                // every node on the way to the root is merged by one of the
+                // proofs, so the siblings are known
+                lemmas.push(nodes[&sibling].clone());
+            }
+            queue.push((index.parent(), descendant));
+        }
+        indices.sort_by_key(|i| &nodes[i]);
+
+        Ok(MerkleProof::new(indices, lemmas))
+    }
+
+    /// Splits the proof into a standalone proof per leaf, the lemmas of each are
+    /// taken from the nodes calculated while verifying this proof.
+    ///
+    /// Returns pairs of leaf and its proof, in the order of `indices`.
+    pub fn split(&self, leaves: &[T]) -> Option<Vec<(T, Self)>> {
+        self.try_split(leaves).ok()
+    }
+
+    /// Same as `split`, but returns the reason of the failure.
+    pub fn try_split(&self, leaves: &[T]) -> Result<Vec<(T, Self)>, MerkleError> {
+        let nodes = self.try_compute_nodes(leaves)?;
+
+        // `indices` are in the order of the hashes of the leaves
+        let mut leaves = leaves
+            .iter()
+            .map(|leaf| (M::hash_leaf(leaf), leaf.clone()))
+            .collect::<Vec<_>>();
+        leaves.sort_by(|a, b| a.0.cmp(&b.0));
+
+        Ok(self
+            .indices
+            .iter()
+            .zip(leaves)
+            .map(|(index, (_, leaf))| {
+                let lemmas = collect_lemmas(&[*index], |sibling| nodes[&sibling].clone());
+                (leaf, MerkleProof::new(vec![*index], lemmas))
+            })
+            .collect())
+    }
+
+    /// Calculates the root like `root`, and returns every node merged on the way
+    /// by node index, including the hashed leaves, the lemmas and the root, e.g.
+    /// to cache a partial tree.
+    pub fn compute_nodes(&self, leaves: &[T]) -> Option<BTreeMap<u32, T>> {
+        self.try_compute_nodes(leaves).ok()
+    }
+
+    /// Same as `compute_nodes`, but returns the reason of the failure.
+    pub fn try_compute_nodes(&self, leaves: &[T]) -> Result<BTreeMap<u32, T>, MerkleError> {
+        if self.indices.is_empty() {
+            return Err(MerkleError::EmptyProof);
+        }
+        if leaves.len() != self.indices.len() {
+            return Err(MerkleError::LeafCountMismatch {
+                expected: self.indices.len(),
+                actual: leaves.len(),
+            });
+        }
+
+        let mut nodes = BTreeMap::new();
+        visit_nodes::<T, M, _>(
+            &mut self.pair_leaves(leaves),
+            &self.lemmas,
+            |index, node| {
+                nodes.insert(index, node.clone());
+            },
+        )?;
+        Ok(nodes)
+    }
+}
+
+/// Pops the node with the greatest index, which must not be both merged from a
+/// descendant and one of the indices.
+fn pop_node(queue: &mut BinaryHeap<(u32, u32)>) -> Result<Option<(u32, u32)>, MerkleError> {
+    let node = queue.pop();
+    match (node, queue.peek()) {
+        // the merged node is paired with a greater index than the index itself
+        (Some((index, descendant)), Some((next, _))) if *next == index => {
+            Err(MerkleError::OverlappingIndices {
+                ancestor: index,
+                descendant,
+            })
+        }
+        _ => Ok(node),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{leaves_and_indices, leaves_and_two_indices, MergeI32, CBMTI32};
+    use proptest::proptest;
+
+    #[test]
+    fn merge_proofs() {
+        let leaves = vec![2i32, 3, 5, 7, 11, 13, 17];
+        let tree = CBMTI32::build_merkle_tree(&leaves);
+
+        let proof_a = tree.build_proof(&[0, 4]).unwrap();
+        let proof_b = tree.build_proof(&[1, 4]).unwrap();
+        let merged = proof_a.merge(&proof_b, &[2, 11], &[3, 11]).unwrap();
+
+        let expected = tree.build_proof(&[0, 1, 4]).unwrap();
+        assert_eq!(expected.indices(), merged.indices());
+        assert_eq!(expected.lemmas(), merged.lemmas());
+        assert!(merged.lemmas().len() < proof_a.lemmas().len() + proof_b.lemmas().len());
+        assert!(merged.verify(&tree.root(), &[3, 2, 11]));
+    }
+
+    #[test]
+    fn merge_proofs_of_different_trees() {
+        let tree_a = CBMTI32::build_merkle_tree(&[2, 3, 5, 7, 11]);
+        let tree_b = CBMTI32::build_merkle_tree(&[2, 3, 5, 7, 12]);
+
+        let proof_a = tree_a.build_proof(&[0]).unwrap();
+        let proof_b = tree_b.build_proof(&[1]).unwrap();
+        assert_eq!(
+            Some(MerkleError::NodeMismatch { index: 0 }),
+            proof_a.try_merge(&proof_b, &[2], &[3]).err()
+        );
+        assert_eq!(
+            Some(MerkleError::LeafCountMismatch {
+                expected: 1,
+                actual: 2
+            }),
+            proof_a.try_merge(&proof_a, &[2], &[2, 3]).err()
+        );
+    }
+
+    #[test]
+    fn merge_overlapping_proofs() {
+        let tree = CBMTI32::build_merkle_tree(&[2, 3, 5, 7]);
+        let nodes = tree.nodes();
+
+        // the interior node 1 presented as a leaf verifies on its own
+        let forged = MerkleProof::<i32, MergeI32>::new(vec![1], vec![nodes[2]]);
+        assert!(forged.verify(&tree.root(), &[nodes[1]]));
+
+        let proof = tree.build_proof(&[0]).unwrap();
+        assert_eq!(
+            Some(MerkleError::OverlappingIndices {
+                ancestor: 1,
+                descendant: 3
+            }),
+            forged.try_merge(&proof, &[nodes[1]], &[2]).err()
+        );
+        assert_eq!(
+            Some(MerkleError::OverlappingIndices {
+                ancestor: 1,
+                descendant: 3
+            }),
+            proof.try_merge(&forged, &[2], &[nodes[1]]).err()
+        );
+    }
+
+    #[test]
+    fn split_proof() {
+        let leaves = vec![2i32, 3, 5, 7, 11, 13, 17];
+        let tree = CBMTI32::build_merkle_tree(&leaves);
+        let proof = tree.build_proof(&[5, 0, 3]).unwrap();
+
+        let proofs = proof.split(&[13, 2, 7]).unwrap();
+        assert_eq!(
+            vec![2, 7, 13],
+            proofs.iter().map(|(leaf, _)| *leaf).collect::<Vec<_>>()
+        );
+        for ((leaf, single_proof), leaf_index) in proofs.iter().zip(&[0, 3, 5]) {
+            let expected = tree.build_proof(&[*leaf_index]).unwrap();
+            assert_eq!(expected.indices(), single_proof.indices());
+            assert_eq!(expected.lemmas(), single_proof.lemmas());
+            assert!(single_proof.verify(&tree.root(), &[*leaf]));
+        }
+
+        assert_eq!(
+            Some(MerkleError::LemmaCountMismatch),
+            MerkleProof::<i32, MergeI32>::new(proof.indices().to_vec(), vec![])
+                .try_split(&[13, 2, 7])
+                .err()
+        );
+    }
+
+    fn _split_proof_is_same_as_built_proof(leaves: Vec<i32>, leaf_indices: Vec<u32>) {
+        let tree = CBMTI32::build_merkle_tree(&leaves);
+        let proof = tree.build_proof(&leaf_indices).unwrap();
+        let proof_leaves = leaf_indices
+            .iter()
+            .map(|i| leaves[*i as usize])
+            .collect::<Vec<_>>();
+
+        let proofs = proof.split(&proof_leaves).unwrap();
+        assert_eq!(leaf_indices.len(), proofs.len());
+        for (leaf, single_proof) in proofs {
+            let leaf_index = tree.node_to_leaf_index(single_proof.indices()[0]).unwrap();
+            assert_eq!(leaves[leaf_index as usize], leaf);
+            let expected = tree.build_proof(&[leaf_index]).unwrap();
+            assert_eq!(expected.lemmas(), single_proof.lemmas());
+            assert!(single_proof.verify(&tree.root(), &[leaf]));
+        }
+    }
+
+    #[test]
+    fn compute_nodes() {
+        let leaves = vec![2i32, 3, 5, 7, 11];
+        let tree = CBMTI32::build_merkle_tree(&leaves);
+        let proof = tree.build_proof(&[0, 3]).unwrap();
+
+        let nodes = proof.compute_nodes(&[2, 7]).unwrap();
+        assert_eq!(
+            vec![0, 1, 2, 3, 4, 7, 8],
+            nodes.keys().copied().collect::<Vec<_>>()
+        );
+        for (index, node) in nodes {
+            assert_eq!(tree.nodes()[index as usize], node);
+        }
+
+        assert_eq!(
+            Some(MerkleError::EmptyProof),
+            MerkleProof::<i32, MergeI32>::new(vec![], vec![])
+                .try_compute_nodes(&[])
+                .err()
+        );
+    }
+
+    fn _computed_nodes_are_tree_nodes(leaves: Vec<i32>, leaf_indices: Vec<u32>) {
+        let tree = CBMTI32::build_merkle_tree(&leaves);
+        let proof = tree.build_proof(&leaf_indices).unwrap();
+        let proof_leaves = leaf_indices
+            .iter()
+            .map(|i| leaves[*i as usize])
+            .collect::<Vec<_>>();
+
+        let nodes = proof.compute_nodes(&proof_leaves).unwrap();
+        assert_eq!(Some(&tree.root()), nodes.get(&0));
+        for index in proof.indices() {
+            assert!(nodes.contains_key(index));
+        }
+        for (index, node) in nodes {
+            assert_eq!(tree.nodes()[index as usize], node);
+        }
+    }
+
+    fn _merged_proof_is_same_as_built_proof(
+        leaves: Vec<i32>,
+        leaf_indices_a: Vec<u32>,
+        leaf_indices_b: Vec<u32>,
+    ) {
+        let tree = CBMTI32::build_merkle_tree(&leaves);
+        let proof_leaves = |leaf_indices: &[u32]| {
+            leaf_indices
+                .iter()
+                .map(|i| leaves[*i as usize])
+                .collect::<Vec<_>>()
+        };
+        let proof_a = tree.build_proof(&leaf_indices_a).unwrap();
+        let proof_b = tree.build_proof(&leaf_indices_b).unwrap();
+        let merged = proof_a
+            .merge(
+                &proof_b,
+                &proof_leaves(&leaf_indices_a),
+                &proof_leaves(&leaf_indices_b),
+            )
+            .unwrap();
+
+        let mut leaf_indices = leaf_indices_a;
+        leaf_indices.extend(leaf_indices_b);
+        leaf_indices.sort_unstable();
+        leaf_indices.dedup();
+        let expected = tree.build_proof(&leaf_indices).unwrap();
+        assert_eq!(expected.lemmas(), merged.lemmas());
+        assert_eq!(Some(tree.root()), merged.root(&proof_leaves(&leaf_indices)));
+    }
+
+    proptest! {
+        #[test]
+        fn merged_proof_is_same_as_built_proof(input in leaves_and_two_indices(2..500)) {
+            _merged_proof_is_same_as_built_proof(input.0, input.1, input.2);
+        }
+
+        #[test]
+        fn split_proof_is_same_as_built_proof(input in leaves_and_indices(2..500)) {
+            _split_proof_is_same_as_built_proof(input.0, input.1);
+        }
+
+        #[test]
+        fn computed_nodes_are_tree_nodes(input in leaves_and_indices(2..500)) {
+            _computed_nodes_are_tree_nodes(input.0, input.1);
+        }
+    }
+}
@@ -0,0 +1,308 @@
+use crate::error::MerkleError;
+use crate::merkle_tree::{
+    collect_lemmas, leaf_index_to_node, node_to_leaf_index, Merge, MerkleProof,
+};
+use crate::proof_ref::calculate_root;
+use crate::{vec, vec::Vec};
+use core::cmp::Reverse;
+use core::marker::PhantomData;
+
+/// A compact form of `MerkleProof`, the proved leaves are marked in a bitmap of
+/// leaf positions instead of listing their node indices.
+///
+/// A bitmap takes `ceil(leaves_count / 8)` bytes, while the indices take 4 bytes
+/// per proved leaf, so it is smaller once more than `leaves_count / 32` leaves
+/// are proved.
+///
+/// Bit `i` of the bitmap, which is bit `i % 8` of byte `i / 8`, marks the leaf at
+/// position `i`. Leaves are always supplied in the order of their positions.
+pub struct BitmapProof<T, M> {
+    leaves_count: u32,
+    bitmap: Vec<u8>,
+    lemmas: Vec<T>,
+    merge: PhantomData<M>,
+}
+
+impl<T, M> BitmapProof<T, M>
+where
+    T: Ord + Default + Clone,
+    M: Merge<Item = T>,
+{
+    /// The bitmap must have exactly `ceil(leaves_count / 8)` bytes, and the bits
+    /// beyond `leaves_count` must be zero.
+    pub fn new(leaves_count: u32, bitmap: Vec<u8>, lemmas: Vec<T>) -> Result<Self, MerkleError> {
+        let bitmap_len = ((leaves_count as usize) + 7) >> 3;
+        if bitmap.len() != bitmap_len {
+            return Err(MerkleError::InvalidBitmap);
+        }
+        if leaves_count & 7 != 0 && bitmap[bitmap_len - 1] >> (leaves_count & 7) != 0 {
+            return Err(MerkleError::InvalidBitmap);
+        }
+        Ok(BitmapProof {
+            leaves_count,
+            bitmap,
+            lemmas,
+            merge: PhantomData,
+        })
+    }
+
+    /// Converts a proof of a tree with `leaves_count` leaves.
+    pub fn from_merkle_proof(
+        proof: &MerkleProof<T, M>,
+        leaves_count: u32,
+    ) -> Result<Self, MerkleError> {
+        if leaves_count == 0 {
+            return Err(MerkleError::EmptyTree);
+        }
+        let mut bitmap = vec![0u8; ((leaves_count as usize) + 7) >> 3];
+        for index in proof.indices() {
+            let position = node_to_leaf_index(leaves_count, *index)
+                .ok_or(MerkleError::NodeIndexOutOfRange { index: *index })?;
+            let (byte, bit) = ((position >> 3) as usize, position & 7);
+            if bitmap[byte] & (1 << bit) != 0 {
+                return Err(MerkleError::DuplicateNodeIndex { index: *index });
+            }
+            bitmap[byte] |= 1 << bit;
+        }
+        Ok(BitmapProof {
+            leaves_count,
+            bitmap,
+            lemmas: proof.lemmas().to_vec(),
+            merge: PhantomData,
+        })
+    }
+
+    /// Converts back to a `MerkleProof`, `leaves` are used to order the indices by
+    /// their hashes as `MerkleTree::build_proof` does.
+    pub fn to_merkle_proof(&self, leaves: &[T]) -> Result<MerkleProof<T, M>, MerkleError> {
+        let positions = self.leaf_positions();
+        if leaves.len() != positions.len() {
+            return Err(MerkleError::LeafCountMismatch {
+                expected: positions.len(),
+                actual: leaves.len(),
+            });
+        }
+
+        let mut indexed_leaves = self.index_leaves(&positions, leaves)?;
+        indexed_leaves.sort_by_key(|(index, _)| Reverse(*index));
+        indexed_leaves.sort_by(|a, b| a.1.cmp(&b.1));
+
+        Ok(MerkleProof::new(
+            indexed_leaves.into_iter().map(|(index, _)| index).collect(),
+            self.lemmas.clone(),
+        ))
+    }
+
+    /// The positions of the proved leaves in ascending order.
+    pub fn leaf_positions(&self) -> Vec<u32> {
+        (0..self.leaves_count)
+            .filter(|position| self.bitmap[(position >> 3) as usize] & (1 << (position & 7)) != 0)
+            .collect()
+    }
+
+    /// Pairs the hashed leaves with the node indices of their positions.
+    fn index_leaves(&self, positions: &[u32], leaves: &[T]) -> Result<Vec<(u32, T)>, MerkleError> {
+        positions
+            .iter()
+            .zip(leaves)
+            .map(|(position, leaf)| {
+                let index = leaf_index_to_node(self.leaves_count, *position)
+                    .ok_or(MerkleError::IndexOutOfRange { index: *position })?;
+                Ok((index, M::hash_leaf(leaf)))
+            })
+            .collect()
+    }
+
+    /// `leaves`: the proved leaves in the order of their positions
+    /// `leaves_count`: the number of leaves of the tree, from a trusted source,
+    /// since the root doesn't commit to it
+    pub fn root(&self, leaves: &[T], leaves_count: u32) -> Option<T> {
+        self.try_root(leaves, leaves_count).ok()
+    }
+
+    /// Same as `root`, but returns the reason of the failure.
+    ///
+    /// The proof must have exactly the lemmas `MerkleTree::build_proof` produces
+    /// for the positions.
+    pub fn try_root(&self, leaves: &[T], leaves_count: u32) -> Result<T, MerkleError> {
+        if self.leaves_count != leaves_count {
+            return Err(MerkleError::TreeSizeMismatch {
+                expected: leaves_count,
+                actual: self.leaves_count,
+            });
+        }
+        let positions = self.leaf_positions();
+        if positions.is_empty() {
+            return Err(MerkleError::EmptyProof);
+        }
+        if leaves.len() != positions.len() {
+            return Err(MerkleError::LeafCountMismatch {
+                expected: positions.len(),
+                actual: leaves.len(),
+            });
+        }
+
+        let mut pre = self.index_leaves(&positions, leaves)?;
+        let indices = pre
+            .iter()
+            .rev()
+            .map(|(index, _)| *index)
+            .collect::<Vec<_>>();
+        let lemmas_count = collect_lemmas(&indices, |_| ()).len();
+        if self.lemmas.len() < lemmas_count {
+            return Err(MerkleError::LemmaCountMismatch);
+        }
+        if self.lemmas.len() > lemmas_count {
+            return Err(MerkleError::UnconsumedLemmas {
+                count: self.lemmas.len() - lemmas_count,
+            });
+        }
+
+        calculate_root::<T, M>(&mut pre, &self.lemmas)
+    }
+
+    pub fn verify(&self, root: &T, leaves: &[T], leaves_count: u32) -> bool {
+        match self.root(leaves, leaves_count) {
+            Some(r) => &r == root,
+            _ => false,
+        }
+    }
+
+    pub fn leaves_count(&self) -> u32 {
+        self.leaves_count
+    }
+
+    pub fn bitmap(&self) -> &[u8] {
+        &self.bitmap
+    }
+
+    pub fn lemmas(&self) -> &[T] {
+        &self.lemmas
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{leaves_and_indices, MergeI32, CBMTI32};
+    use proptest::proptest;
+
+    type BitmapProofI32 = BitmapProof<i32, MergeI32>;
+
+    #[test]
+    fn bitmap_proof() {
+        let leaves = vec![2i32, 3, 5, 7, 11, 13, 17, 19, 23];
+        let root = CBMTI32::build_merkle_root(&leaves);
+        let proof = CBMTI32::build_merkle_proof(&leaves, &[8, 0, 5]).unwrap();
+
+        let bitmap_proof = BitmapProofI32::from_merkle_proof(&proof, 9).unwrap();
+        assert_eq!(&[0b0010_0001, 0b0000_0001], bitmap_proof.bitmap());
+        assert_eq!(vec![0, 5, 8], bitmap_proof.leaf_positions());
+        assert!(bitmap_proof.verify(&root, &[2, 13, 23], 9));
+        assert!(!bitmap_proof.verify(&root, &[13, 2, 23], 9));
+
+        let converted = bitmap_proof.to_merkle_proof(&[2, 13, 23]).unwrap();
+        assert_eq!(proof.indices(), converted.indices());
+        assert_eq!(proof.lemmas(), converted.lemmas());
+    }
+
+    #[test]
+    fn invalid_bitmap_proof() {
+        assert_eq!(
+            Some(MerkleError::InvalidBitmap),
+            BitmapProofI32::new(9, vec![1], vec![]).err()
+        );
+        assert_eq!(
+            Some(MerkleError::InvalidBitmap),
+            BitmapProofI32::new(9, vec![1, 2], vec![]).err()
+        );
+        assert!(BitmapProofI32::new(8, vec![0xff], vec![]).is_ok());
+
+        let proof = MerkleProof::<i32, MergeI32>::new(vec![7, 3], vec![]);
+        assert_eq!(
+            Some(MerkleError::NodeIndexOutOfRange { index: 3 }),
+            BitmapProofI32::from_merkle_proof(&proof, 5).err()
+        );
+        assert_eq!(
+            Some(MerkleError::NodeIndexOutOfRange { index: 7 }),
+            BitmapProofI32::from_merkle_proof(&proof, 3).err()
+        );
+        let proof = MerkleProof::<i32, MergeI32>::new(vec![7, 7], vec![]);
+        assert_eq!(
+            Some(MerkleError::DuplicateNodeIndex { index: 7 }),
+            BitmapProofI32::from_merkle_proof(&proof, 5).err()
+        );
+    }
+
+    #[test]
+    fn forged_bitmap_proof() {
+        let leaves = vec![2i32, 3, 5, 7, 11];
+        let tree = CBMTI32::build_merkle_tree(&leaves);
+        let root = tree.root();
+        let proof = tree.build_proof(&[4]).unwrap();
+
+        // leaf 4 of 5 is at the same node as leaf 0 of 9
+        let forged = BitmapProofI32::new(9, vec![1, 0], proof.lemmas().to_vec()).unwrap();
+        assert!(!forged.verify(&root, &[11], 5));
+        assert_eq!(
+            Some(MerkleError::TreeSizeMismatch {
+                expected: 5,
+                actual: 9
+            }),
+            forged.try_root(&[11], 5).err()
+        );
+
+        let mut lemmas = proof.lemmas().to_vec();
+        lemmas.push(0);
+        let bitmap_proof = BitmapProofI32::new(5, vec![0b1_0000], lemmas).unwrap();
+        assert_eq!(
+            Some(MerkleError::UnconsumedLemmas { count: 1 }),
+            bitmap_proof.try_root(&[11], 5).err()
+        );
+        let bitmap_proof = BitmapProofI32::new(5, vec![0b1_0000], vec![]).unwrap();
+        assert_eq!(
+            Some(MerkleError::LemmaCountMismatch),
+            bitmap_proof.try_root(&[11], 5).err()
+        );
+    }
+
+    #[test]
+    fn bitmap_proof_size() {
+        let leaves = (0..1000).collect::<Vec<i32>>();
+        let leaf_indices = (0..1000).step_by(4).collect::<Vec<u32>>();
+        let proof = CBMTI32::build_merkle_proof(&leaves, &leaf_indices).unwrap();
+        let bitmap_proof = BitmapProofI32::from_merkle_proof(&proof, 1000).unwrap();
+
+        // 250 indices of 4 bytes vs 4 bytes of count and 125 bytes of bitmap
+        assert_eq!(1000, proof.indices().len() * 4);
+        assert_eq!(129, 4 + bitmap_proof.bitmap().len());
+        assert_eq!(proof.lemmas(), bitmap_proof.lemmas());
+    }
+
+    fn _bitmap_proof_round_trip(leaves: Vec<i32>, mut leaf_indices: Vec<u32>) {
+        let proof = CBMTI32::build_merkle_proof(&leaves, &leaf_indices).unwrap();
+        let bitmap_proof = BitmapProofI32::from_merkle_proof(&proof, leaves.len() as u32).unwrap();
+
+        leaf_indices.sort_unstable();
+        assert_eq!(leaf_indices, bitmap_proof.leaf_positions());
+        let proof_leaves = leaf_indices
+            .iter()
+            .map(|i| leaves[*i as usize])
+            .collect::<Vec<_>>();
+        assert_eq!(
+            Some(CBMTI32::build_merkle_root(&leaves)),
+            bitmap_proof.root(&proof_leaves, leaves.len() as u32)
+        );
+
+        let converted = bitmap_proof.to_merkle_proof(&proof_leaves).unwrap();
+        assert_eq!(proof.indices(), converted.indices());
+        assert_eq!(proof.lemmas(), converted.lemmas());
+    }
+
+    proptest! {
+        #[test]
+        fn bitmap_proof_round_trip(input in leaves_and_indices(2..1000)) {
+            _bitmap_proof_round_trip(input.0, input.1);
+        }
+    }
+}
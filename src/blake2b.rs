@@ -0,0 +1,111 @@
+//! CKB compatible Blake2b-256 merge, enabled by the `blake2b` feature.
+
+use crate::merkle_tree::{Merge, MixInLength};
+use crate::{length_to_h256, H256};
+use blake2b_ref::{Blake2b, Blake2bBuilder};
+
+/// The personalization used by CKB's default hash function.
+pub const CKB_HASH_PERSONALIZATION: &[u8] = b"ckb-default-hash";
+
+fn new_blake2b() -> Blake2b {
+    Blake2bBuilder::new(32)
+        .personal(CKB_HASH_PERSONALIZATION)
+        .build()
+}
+
+/// Blake2b-256 hash of `data` with the CKB personalization.
+pub fn blake2b_256(data: &[u8]) -> H256 {
+    let mut result = [0u8; 32];
+    let mut blake2b = new_blake2b();
+    blake2b.update(data);
+    blake2b.finalize(&mut result);
+    result
+}
+
+/// Merges two nodes as `blake2b_256(left || right)`, the same way CKB computes
+/// the transactions root of a block.
+pub struct Blake2bMerge;
+
+impl Merge for Blake2bMerge {
+    type Item = H256;
+    fn merge(left: &Self::Item, right: &Self::Item) -> Self::Item {
+        let mut result = [0u8; 32];
+        let mut blake2b = new_blake2b();
+        blake2b.update(left);
+        blake2b.update(right);
+        blake2b.finalize(&mut result);
+        result
+    }
+}
+
+impl MixInLength for Blake2bMerge {
+    fn hash_length(leaves_count: u32) -> Self::Item {
+        length_to_h256(leaves_count)
+    }
+}
+
+pub type CBMT = crate::CBMT<H256, Blake2bMerge>;
+#[cfg(feature = "alloc")]
+pub type MerkleTree = crate::MerkleTree<H256, Blake2bMerge>;
+#[cfg(feature = "alloc")]
+pub type MerkleProof = crate::MerkleProof<H256, Blake2bMerge>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::h256;
+
+    // CKB's transactions root is `merge(raw_transactions_root, witnesses_root)`,
+    // where both are CBMT roots over the transaction hashes and the witness hashes.
+    fn transactions_root(tx_hashes: &[H256], witness_hashes: &[H256]) -> H256 {
+        CBMT::build_merkle_root(&[
+            CBMT::build_merkle_root(tx_hashes),
+            CBMT::build_merkle_root(witness_hashes),
+        ])
+    }
+
+    #[test]
+    fn blake2b_empty() {
+        assert_eq!(
+            h256("0x44f4c69744d5f8c55d642062949dcae49bc4e7ef43d388c5a12f42b5633d163e"),
+            blake2b_256(&[])
+        );
+    }
+
+    #[test]
+    fn mainnet_genesis_transactions_root() {
+        // block 0x92b197aa1fba0f63633922c61c92375c9c074a93e85963554f5499fe1450d0e5
+        let tx_hashes = [
+            h256("0xe2fb199810d49a4d8beec56718ba2593b665db9d52299a0f9e6e75416d73ff5c"),
+            h256("0x71a7ba8fc96349fea0ed3a5c47992e3b4084b031a42264a018e0072e8172e46c"),
+        ];
+        let witness_hashes = [
+            h256("0xc8bd7ebec70b48035cbb52abbad05eb381cbc7c24a1cbb13d810bf842d65947f"),
+            h256("0xa228374cc5702bd67ca414c3674199b975b917577479c3a93c44690217461d4e"),
+        ];
+        assert_eq!(
+            h256("0x31bf3fdf4bc16d6ea195dbae808e2b9a8eca6941d589f6959b1d070d51ac28f7"),
+            transactions_root(&tx_hashes, &witness_hashes)
+        );
+    }
+
+    #[test]
+    fn testnet_genesis_transactions_root() {
+        // block 0x10639e0895502b5688a6be8cf69460d76541bfa4821629d86d62ba0aae3f9606
+        let tx_hashes = [
+            h256("0x8f8c79eb6671709633fe6a46de93c0fedc9c1b8a6527a18d3983879542635c9f"),
+            h256("0xf8de3bb47d055cdf460d93a2a6e1b05f7432f9777c8c474abf4eec1d4aee5d37"),
+        ];
+        let witness_hashes = [
+            h256("0xafe5b7ccb34df88dd4fd5a01e5198684f7b12b24ca07e2fb10635ac9ed35a36e"),
+            h256("0xab266d8849b051c781265ad99e4e21340542de0887f2849be5d8a63ddf35d7d1"),
+        ];
+        let root = h256("0x00e5d0a4869bc21533d7487ee2377b514245bdfca3ac30ba0710e608011760f6");
+        assert_eq!(root, transactions_root(&tx_hashes, &witness_hashes));
+
+        // the proof of the cellbase against the raw transactions root
+        let raw_transactions_root = CBMT::build_merkle_root(&tx_hashes);
+        let proof = CBMT::build_merkle_proof(&tx_hashes, &[0]).unwrap();
+        assert!(proof.verify(&raw_transactions_root, &tx_hashes[0..1]));
+    }
+}
@@ -0,0 +1,194 @@
+use crate::merkle_tree::{Merge, MerkleTree};
+use crate::{vec, vec::Vec};
+use core::marker::PhantomData;
+
+/// Builds a CBMT by appending leaves one at a time.
+///
+/// With `n` leaves and `p` the largest power of two not greater than `n`, the
+/// first `2p - n` leaves stay at the level above the bottom, and the last
+/// `2(n - p)` leaves are paired at the bottom level. Every push shifts the
+/// positions of the leaves in the tree, so the subtrees of a CBMT can't be
+/// reused as such, but each of them is still the perfect tree of some `2^h`
+/// consecutive leaves.
+///
+/// The root of the perfect tree of `2^h` leaves starting at each leaf is cached
+/// when the last of them is pushed, which takes `O(log n)` merges per push and
+/// `O(n log n)` cached nodes. `root` then picks one cached subtree per level and
+/// merges them in `O(log n)`.
+pub struct CbmtBuilder<T, M> {
+    // `windows[h][i]` is the root of the perfect tree of leaves `i..i + 2^h`,
+    // `windows[0]` are the hashed leaves
+    windows: Vec<Vec<T>>,
+    merge: PhantomData<M>,
+}
+
+impl<T, M> Default for CbmtBuilder<T, M>
+where
+    T: Ord + Default + Clone,
+    M: Merge<Item = T>,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, M> CbmtBuilder<T, M>
+where
+    T: Ord + Default + Clone,
+    M: Merge<Item = T>,
+{
+    pub fn new() -> Self {
+        Self::with_capacity(0)
+    }
+
+    pub fn with_capacity(capacity: usize) -> Self {
+        CbmtBuilder {
+            windows: vec![Vec::with_capacity(capacity)],
+            merge: PhantomData,
+        }
+    }
+
+    pub fn push(&mut self, leaf: T) {
+        self.windows[0].push(M::hash_leaf(&leaf));
+        let len = self.windows[0].len();
+        // the windows ending at the new leaf
+        let mut h = 1;
+        while 1 << h <= len {
+            let start = len - (1 << h);
+            let half = &self.windows[h - 1];
+            let window = M::merge(&half[start], &half[start + (1 << (h - 1))]);
+            if self.windows.len() == h {
+                self.windows.push(Vec::new());
+            }
+            self.windows[h].push(window);
+            h += 1;
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.windows[0].len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.windows[0].is_empty()
+    }
+
+    /// The root of the leaves pushed so far, the same as `CBMT::build_merkle_root`.
+    pub fn root(&self) -> T {
+        let len = self.len();
+        if len == 0 {
+            return T::default();
+        }
+        let h = (usize::BITS - 1 - len.leading_zeros()) as usize;
+        let p = 1 << h;
+        self.subtree_root(h, len - p, 2 * p - len)
+    }
+
+    /// The root of the perfect tree above `2^h` nodes, which are `pairs` merges of
+    /// two leaves starting at leaf `start`, followed by the leaves from 0 to
+    /// `2^h - pairs`.
+    fn subtree_root(&self, h: usize, pairs: usize, start: usize) -> T {
+        if h == 0 {
+            // `pairs` is always less than `2^h`
+            return self.windows[0][0].clone();
+        }
+        let half = 1 << (h - 1);
+        if pairs >= half {
+            // the left half is `2^h` leaves from `start`
+            let left = &self.windows[h][start];
+            M::merge(
+                left,
+                &self.subtree_root(h - 1, pairs - half, start + (1 << h)),
+            )
+        } else {
+            // the right half is the last `2^(h - 1)` of the leaves from 0
+            let singles = (1 << h) - pairs;
+            let right = &self.windows[h - 1][singles - half];
+            M::merge(&self.subtree_root(h - 1, pairs, start), right)
+        }
+    }
+
+    /// Builds the tree, the same as `CBMT::build_merkle_tree`.
+    pub fn finish(mut self) -> MerkleTree<T, M> {
+        let len = self.len();
+        if len == 0 {
+            return MerkleTree {
+                nodes: vec![],
+                merge: PhantomData,
+            };
+        }
+
+        // the leaves from `singles` are merged in pairs to nodes `p - 1..len - 1`
+        let p = 1 << (usize::BITS - 1 - len.leading_zeros());
+        let singles = 2 * p - len;
+        let mut nodes = vec![T::default(); p - 1];
+        if singles < len {
+            nodes.extend(self.windows[1][singles..].iter().step_by(2).cloned());
+        }
+        nodes.append(&mut self.windows[0]);
+
+        (0..p - 1)
+            .rev()
+            .for_each(|i| nodes[i] = M::merge(&nodes[(i << 1) + 1], &nodes[(i << 1) + 2]));
+
+        MerkleTree {
+            nodes,
+            merge: PhantomData,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{HashLeafCBMTI32, HashLeafMergeI32};
+    use proptest::collection::vec;
+    use proptest::num::i32;
+    use proptest::proptest;
+
+    type CbmtBuilderI32 = CbmtBuilder<i32, HashLeafMergeI32>;
+
+    #[test]
+    fn build_empty() {
+        let builder = CbmtBuilderI32::new();
+        assert_eq!(0, builder.root());
+        assert!(builder.finish().nodes().is_empty());
+    }
+
+    #[test]
+    fn build_five() {
+        let leaves = vec![2i32, 3, 5, 7, 11];
+        let mut builder = CbmtBuilderI32::new();
+        for leaf in &leaves {
+            builder.push(*leaf);
+        }
+        assert_eq!(5, builder.len());
+        assert_eq!(HashLeafCBMTI32::build_merkle_root(&leaves), builder.root());
+        assert_eq!(
+            HashLeafCBMTI32::build_merkle_tree(&leaves).nodes(),
+            builder.finish().nodes()
+        );
+    }
+
+    fn _builder_is_same_as_batch(leaves: Vec<i32>) {
+        let mut builder = CbmtBuilderI32::with_capacity(leaves.len());
+        for (i, leaf) in leaves.iter().enumerate() {
+            builder.push(*leaf);
+            assert_eq!(
+                HashLeafCBMTI32::build_merkle_root(&leaves[..=i]),
+                builder.root()
+            );
+        }
+        assert_eq!(
+            HashLeafCBMTI32::build_merkle_tree(&leaves).nodes(),
+            builder.finish().nodes()
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn builder_is_same_as_batch(leaves in vec(i32::ANY, 0..300)) {
+            _builder_is_same_as_batch(leaves);
+        }
+    }
+}
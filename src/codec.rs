@@ -0,0 +1,347 @@
+//! Compact binary encoding of `MerkleProof`.
+//!
+//! Version 1 of the encoding is laid out as:
+//!
+//! ```text
+//! version           u8, always 1
+//! indices count     varint
+//! indices           u32 little endian, repeated `indices count` times
+//! lemmas count      varint
+//! lemmas            `T::SIZE` bytes each, repeated `lemmas count` times
+//! ```
+//!
+//! Counts are unsigned LEB128 varints of at most 5 bytes which must be encoded in
+//! the shortest form. Decoding is strict: counts which don't fit in the remaining
+//! bytes and trailing bytes after the last lemma are rejected.
+
+use crate::merkle_tree::{Merge, MerkleProof};
+use crate::vec::Vec;
+use core::fmt;
+
+/// The current version of the proof encoding.
+pub const PROOF_ENCODING_VERSION: u8 = 1;
+
+const INDEX_SIZE: usize = 4;
+const MAX_VARINT_SIZE: usize = 5;
+
+/// Types which are encoded as a fixed number of bytes.
+pub trait FixedBytes: Sized {
+    /// The number of bytes of the encoding.
+    const SIZE: usize;
+
+    /// Writes the encoding into `dst`, whose length is `SIZE`.
+    fn write_bytes(&self, dst: &mut [u8]);
+
+    /// Reads from `src`, whose length is `SIZE`, returning `None` if the bytes are invalid.
+    fn read_bytes(src: &[u8]) -> Option<Self>;
+}
+
+impl<const N: usize> FixedBytes for [u8; N] {
+    const SIZE: usize = N;
+
+    fn write_bytes(&self, dst: &mut [u8]) {
+        dst.copy_from_slice(self);
+    }
+
+    fn read_bytes(src: &[u8]) -> Option<Self> {
+        let mut result = [0u8; N];
+        result.copy_from_slice(src);
+        Some(result)
+    }
+}
+
+macro_rules! impl_fixed_bytes {
+    ($t: ty) => {
+        impl FixedBytes for $t {
+            const SIZE: usize = core::mem::size_of::<$t>();
+
+            fn write_bytes(&self, dst: &mut [u8]) {
+                dst.copy_from_slice(&self.to_le_bytes());
+            }
+
+            fn read_bytes(src: &[u8]) -> Option<Self> {
+                let mut bytes = [0u8; core::mem::size_of::<$t>()];
+                bytes.copy_from_slice(src);
+                Some(<$t>::from_le_bytes(bytes))
+            }
+        }
+    };
+}
+
+impl_fixed_bytes!(u32);
+impl_fixed_bytes!(u64);
+impl_fixed_bytes!(i32);
+impl_fixed_bytes!(i64);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The encoding version is not supported.
+    UnsupportedVersion(u8),
+    /// The input ends before the proof is complete.
+    UnexpectedEof,
+    /// A varint is longer than 5 bytes, overflows `u32` or is not in the shortest form.
+    InvalidVarint,
+    /// A count is larger than the remaining bytes can hold.
+    OversizedCount(u32),
+    /// A lemma is rejected by `FixedBytes::read_bytes`.
+    InvalidLemma,
+    /// There are bytes left after the proof.
+    TrailingBytes(usize),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::UnsupportedVersion(version) => {
+                write!(f, "unsupported proof encoding version {}", version)
+            }
+            DecodeError::UnexpectedEof => write!(f, "unexpected end of input"),
+            DecodeError::InvalidVarint => write!(f, "invalid varint"),
+            DecodeError::OversizedCount(count) => write!(f, "oversized count {}", count),
+            DecodeError::InvalidLemma => write!(f, "invalid lemma"),
+            DecodeError::TrailingBytes(len) => write!(f, "{} trailing bytes", len),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for DecodeError {}
+
+fn write_varint(buf: &mut Vec<u8>, mut value: u32) {
+    while value >= 0x80 {
+        buf.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn read(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        if self.bytes.len() < len {
+            return Err(DecodeError::UnexpectedEof);
+        }
+        let (head, tail) = self.bytes.split_at(len);
+        self.bytes = tail;
+        Ok(head)
+    }
+
+    fn read_varint(&mut self) -> Result<u32, DecodeError> {
+        let mut value = 0u64;
+        for i in 0..MAX_VARINT_SIZE {
+            let byte = self.read(1)?[0];
+            value |= u64::from(byte & 0x7f) << (7 * i);
+            if byte & 0x80 == 0 {
+                // reject overflow and encodings which are not in the shortest form
+                if value > u64::from(u32::MAX) || (i > 0 && byte == 0) {
+                    return Err(DecodeError::InvalidVarint);
+                }
+                return Ok(value as u32);
+            }
+        }
+        Err(DecodeError::InvalidVarint)
+    }
+
+    /// Reads a count of items of `item_size` bytes, rejecting counts which don't
+    /// fit in the remaining bytes before anything is allocated.
+    ///
+    /// Zero sized items are counted as one byte, otherwise any count would fit.
+    fn read_count(&mut self, item_size: usize) -> Result<usize, DecodeError> {
+        let count = self.read_varint()?;
+        match (count as usize).checked_mul(item_size.max(1)) {
+            Some(size) if size <= self.bytes.len() => Ok(count as usize),
+            _ => Err(DecodeError::OversizedCount(count)),
+        }
+    }
+}
+
+impl<T, M> MerkleProof<T, M>
+where
+    T: Ord + Default + Clone + FixedBytes,
+    M: Merge<Item = T>,
+{
+    /// Encodes the proof, see the [module documentation](crate::codec) for the format.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let indices = self.indices();
+        let lemmas = self.lemmas();
+        let mut buf = Vec::with_capacity(
+            1 + MAX_VARINT_SIZE * 2 + indices.len() * INDEX_SIZE + lemmas.len() * T::SIZE,
+        );
+        buf.push(PROOF_ENCODING_VERSION);
+
+        write_varint(&mut buf, indices.len() as u32);
+        for index in indices {
+            buf.extend_from_slice(&index.to_le_bytes());
+        }
+
+        write_varint(&mut buf, lemmas.len() as u32);
+        for lemma in lemmas {
+            let start = buf.len();
+            buf.resize(start + T::SIZE, 0);
+            lemma.write_bytes(&mut buf[start..]);
+        }
+        buf
+    }
+
+    /// Decodes a proof encoded by `to_bytes`, the whole input must be consumed.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let mut reader = Reader { bytes };
+
+        let version = reader.read(1)?[0];
+        if version != PROOF_ENCODING_VERSION {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+
+        let indices_count = reader.read_count(INDEX_SIZE)?;
+        let mut indices = Vec::with_capacity(indices_count);
+        for _ in 0..indices_count {
+            let index = u32::read_bytes(reader.read(INDEX_SIZE)?).expect("u32 is always valid");
+            indices.push(index);
+        }
+
+        let lemmas_count = reader.read_count(T::SIZE)?;
+        let mut lemmas = Vec::with_capacity(lemmas_count);
+        for _ in 0..lemmas_count {
+            let lemma = T::read_bytes(reader.read(T::SIZE)?).ok_or(DecodeError::InvalidLemma)?;
+            lemmas.push(lemma);
+        }
+
+        if !reader.bytes.is_empty() {
+            return Err(DecodeError::TrailingBytes(reader.bytes.len()));
+        }
+
+        Ok(MerkleProof::new(indices, lemmas))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{CBMTBytes, MergeBytes};
+    use proptest::collection::vec;
+    use proptest::prelude::*;
+    use proptest::proptest;
+    use proptest::sample::subsequence;
+
+    type BytesProof = MerkleProof<[u8; 4], MergeBytes>;
+
+    struct MergeEmpty {}
+
+    impl Merge for MergeEmpty {
+        type Item = [u8; 0];
+        fn merge(_left: &Self::Item, _right: &Self::Item) -> Self::Item {
+            []
+        }
+    }
+
+    type EmptyProof = MerkleProof<[u8; 0], MergeEmpty>;
+
+    #[test]
+    fn encode_proof() {
+        let proof = BytesProof::new(vec![9, 300], vec![[1, 2, 3, 4], [5, 6, 7, 8]]);
+        assert_eq!(
+            vec![
+                1, // version
+                2, 9, 0, 0, 0, 44, 1, 0, 0, // indices
+                2, 1, 2, 3, 4, 5, 6, 7, 8, // lemmas
+            ],
+            proof.to_bytes()
+        );
+    }
+
+    #[test]
+    fn encode_empty_proof() {
+        let proof = BytesProof::new(vec![], vec![]);
+        let bytes = proof.to_bytes();
+        assert_eq!(vec![1, 0, 0], bytes);
+        let decoded = BytesProof::from_bytes(&bytes).unwrap();
+        assert!(decoded.indices().is_empty());
+        assert!(decoded.lemmas().is_empty());
+    }
+
+    #[test]
+    fn encode_long_varint() {
+        let lemmas = vec![[0u8; 4]; 200];
+        let proof = BytesProof::new(vec![0], lemmas.clone());
+        let bytes = proof.to_bytes();
+        assert_eq!(&[200, 1], &bytes[6..8]);
+        assert_eq!(lemmas, BytesProof::from_bytes(&bytes).unwrap().lemmas());
+    }
+
+    #[test]
+    fn decode_invalid_proof() {
+        assert_eq!(
+            Err(DecodeError::UnexpectedEof),
+            BytesProof::from_bytes(&[]).map(|_| ())
+        );
+        assert_eq!(
+            Err(DecodeError::UnsupportedVersion(2)),
+            BytesProof::from_bytes(&[2, 0, 0]).map(|_| ())
+        );
+        assert_eq!(
+            Err(DecodeError::UnexpectedEof),
+            BytesProof::from_bytes(&[1, 0]).map(|_| ())
+        );
+        assert_eq!(
+            Err(DecodeError::TrailingBytes(1)),
+            BytesProof::from_bytes(&[1, 0, 0, 0]).map(|_| ())
+        );
+        assert_eq!(
+            Err(DecodeError::OversizedCount(2)),
+            BytesProof::from_bytes(&[1, 2, 0, 0, 0, 0, 0]).map(|_| ())
+        );
+        assert_eq!(
+            Err(DecodeError::OversizedCount(u32::MAX)),
+            BytesProof::from_bytes(&[1, 0, 0xff, 0xff, 0xff, 0xff, 0x0f]).map(|_| ())
+        );
+        // zero sized lemmas
+        assert_eq!(
+            Err(DecodeError::OversizedCount(u32::MAX)),
+            EmptyProof::from_bytes(&[1, 0, 0xff, 0xff, 0xff, 0xff, 0x0f]).map(|_| ())
+        );
+        assert_eq!(
+            Err(DecodeError::OversizedCount(2)),
+            EmptyProof::from_bytes(&[1, 0, 2, 0]).map(|_| ())
+        );
+        // overflow
+        assert_eq!(
+            Err(DecodeError::InvalidVarint),
+            BytesProof::from_bytes(&[1, 0xff, 0xff, 0xff, 0xff, 0x1f]).map(|_| ())
+        );
+        // longer than 5 bytes
+        assert_eq!(
+            Err(DecodeError::InvalidVarint),
+            BytesProof::from_bytes(&[1, 0x80, 0x80, 0x80, 0x80, 0x80, 0]).map(|_| ())
+        );
+        // not in the shortest form
+        assert_eq!(
+            Err(DecodeError::InvalidVarint),
+            BytesProof::from_bytes(&[1, 0x80, 0, 0]).map(|_| ())
+        );
+    }
+
+    fn _proof_bytes_round_trip(leaves: Vec<[u8; 4]>, leaf_indices: Vec<u32>) {
+        let proof_leaves = leaf_indices
+            .iter()
+            .map(|i| leaves[*i as usize])
+            .collect::<Vec<_>>();
+
+        let proof = CBMTBytes::build_merkle_proof(&leaves, &leaf_indices).unwrap();
+        let decoded = BytesProof::from_bytes(&proof.to_bytes()).unwrap();
+        assert_eq!(proof.indices(), decoded.indices());
+        assert_eq!(proof.lemmas(), decoded.lemmas());
+        assert!(decoded.verify(&CBMTBytes::build_merkle_root(&leaves), &proof_leaves));
+    }
+
+    proptest! {
+        #[test]
+        fn proof_bytes_round_trip(input in vec(any::<[u8; 4]>(), 2..500)
+            .prop_flat_map(|leaves| (Just(leaves.clone()), subsequence((0..leaves.len() as u32).collect::<Vec<u32>>(), 1..leaves.len())))
+        ) {
+            _proof_bytes_round_trip(input.0, input.1);
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use core::fmt;
+
+/// The reason why a proof can't be built or verified.
+///
+/// A leaf index is the position of a leaf, starting from 0, as passed to
+/// `MerkleTree::build_proof`. A node index is the index in `MerkleTree::nodes`,
+/// as in `MerkleProof::indices`, the leaf at `i` is the node at `i + n - 1` in a
+/// tree of `n` leaves.
+///
+/// New variants may be added in minor releases.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum MerkleError {
+    /// The tree has no nodes.
+    EmptyTree,
+    /// No leaf indices are given to build the proof.
+    EmptyIndices,
+    /// The proof has no indices.
+    EmptyProof,
+    /// The leaf index is out of the range of the leaves.
+    IndexOutOfRange { index: u32 },
+    /// The node index is out of range, or is not a leaf where a leaf is expected.
+    NodeIndexOutOfRange { index: u32 },
+    /// The leaf index appears more than once.
+    DuplicateIndex { index: u32 },
+    /// The node index appears more than once.
+    DuplicateNodeIndex { index: u32 },
+    /// The node index paired with a leaf is not one of the proof indices.
+    IndexNotInProof { index: u32 },
+    /// The number of leaves doesn't match the number of proof indices.
+    LeafCountMismatch { expected: usize, actual: usize },
+    /// The lemmas are exhausted before the root is reached.
+    LemmaCountMismatch,
+    /// Some lemmas are left after the root is reached.
+    UnconsumedLemmas { count: usize },
+    /// The bitmap length doesn't match the leaf count, or bits beyond the leaf
+    /// count are set.
+    InvalidBitmap,
+    /// The leaf at the leaf index is not greater than the previous one.
+    UnsortedLeaves { index: u32 },
+    /// Different nodes are found at the node index, so the proofs are not of the
+    /// same tree.
+    NodeMismatch { index: u32 },
+    /// The node at the node index has not been learned from any proof.
+    UnknownNode { index: u32 },
+    /// A node is proved together with its ancestor, both are node indices.
+    OverlappingIndices { ancestor: u32, descendant: u32 },
+    /// The scratch buffer is smaller than the number of leaves.
+    BufferTooSmall { required: usize },
+    /// Some nodes are left after the root is reached, the indices are not
+    /// consistent with each other, e.g. one is an ancestor of another.
+    UnconsumedNodes { count: usize },
+    /// The leaf count carried by the proof is not the one it is verified against.
+    TreeSizeMismatch { expected: u32, actual: u32 },
+}
+
+impl fmt::Display for MerkleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MerkleError::EmptyTree => write!(f, "the tree is empty"),
+            MerkleError::EmptyIndices => write!(f, "no leaf indices"),
+            MerkleError::EmptyProof => write!(f, "the proof has no indices"),
+            MerkleError::IndexOutOfRange { index } => {
+                write!(f, "leaf index {} is out of range", index)
+            }
+            MerkleError::NodeIndexOutOfRange { index } => {
+                write!(f, "node index {} is out of range", index)
+            }
+            MerkleError::DuplicateIndex { index } => {
+                write!(f, "leaf index {} is duplicated", index)
+            }
+            MerkleError::DuplicateNodeIndex { index } => {
+                write!(f, "node index {} is duplicated", index)
+            }
+            MerkleError::IndexNotInProof { index } => {
+                write!(f, "index {} is not in the proof", index)
+            }
+            MerkleError::LeafCountMismatch { expected, actual } => {
+                write!(f, "expect {} leaves, got {}", expected, actual)
+            }
+            MerkleError::LemmaCountMismatch => write!(f, "not enough lemmas"),
+            MerkleError::UnconsumedLemmas { count } => write!(f, "{} lemmas are not used", count),
+            MerkleError::InvalidBitmap => write!(f, "invalid bitmap"),
+            MerkleError::UnsortedLeaves { index } => {
+                write!(f, "leaf {} is not greater than the previous one", index)
+            }
+            MerkleError::NodeMismatch { index } => {
+                write!(f, "different nodes are found at index {}", index)
+            }
+            MerkleError::UnknownNode { index } => write!(f, "node {} is unknown", index),
+            MerkleError::OverlappingIndices {
+                ancestor,
+                descendant,
+            } => write!(f, "node {} is an ancestor of node {}", ancestor, descendant),
+            MerkleError::BufferTooSmall { required } => {
+                write!(f, "the buffer needs at least {} slots", required)
+            }
+            MerkleError::UnconsumedNodes { count } => write!(f, "{} nodes are not used", count),
+            MerkleError::TreeSizeMismatch { expected, actual } => {
+                write!(f, "expect a tree of {} leaves, got {}", expected, actual)
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for MerkleError {}
@@ -0,0 +1,68 @@
+//! Keccak-256 based merge, enabled by the `keccak256` feature.
+
+use crate::merkle_tree::{Merge, MixInLength};
+use crate::{length_to_h256, H256};
+use sha3::{Digest, Keccak256};
+
+/// Keccak-256 hash of `data`, as used by Ethereum.
+pub fn keccak256(data: &[u8]) -> H256 {
+    Keccak256::digest(data).into()
+}
+
+/// Merges two nodes as `keccak256(left || right)`.
+pub struct Keccak256Merge;
+
+impl Merge for Keccak256Merge {
+    type Item = H256;
+    fn merge(left: &Self::Item, right: &Self::Item) -> Self::Item {
+        let mut hasher = Keccak256::new();
+        hasher.update(left);
+        hasher.update(right);
+        hasher.finalize().into()
+    }
+}
+
+impl MixInLength for Keccak256Merge {
+    fn hash_length(leaves_count: u32) -> Self::Item {
+        length_to_h256(leaves_count)
+    }
+}
+
+pub type CBMT = crate::CBMT<H256, Keccak256Merge>;
+#[cfg(feature = "alloc")]
+pub type MerkleTree = crate::MerkleTree<H256, Keccak256Merge>;
+#[cfg(feature = "alloc")]
+pub type MerkleProof = crate::MerkleProof<H256, Keccak256Merge>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::h256;
+
+    #[test]
+    fn keccak256_empty() {
+        assert_eq!(
+            h256("0xc5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"),
+            keccak256(&[])
+        );
+    }
+
+    #[test]
+    fn keccak256_merge() {
+        assert_eq!(
+            h256("0xad3228b676f7d3cd4284a5443f17f1962b36e491b30a40b2405849e597ba5fb5"),
+            Keccak256Merge::merge(&[0u8; 32], &[0u8; 32])
+        );
+    }
+
+    #[test]
+    fn build_and_verify_proof() {
+        let leaves = [b"a", b"b", b"c", b"d", b"e"]
+            .iter()
+            .map(|leaf| keccak256(&leaf[..]))
+            .collect::<Vec<_>>();
+        let root = CBMT::build_merkle_root(&leaves);
+        let proof = CBMT::build_merkle_proof(&leaves, &[0, 4]).unwrap();
+        assert!(proof.verify(&root, &[leaves[0], leaves[4]]));
+    }
+}
@@ -1,16 +1,178 @@
-#![cfg_attr(not(feature = "std"), no_std)]
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
+// the original tests predate these lints
+#![cfg_attr(test, allow(clippy::bool_assert_comparison, clippy::clone_on_copy))]
 
+#[cfg(feature = "alloc")]
+mod aggregate;
+#[cfg(feature = "alloc")]
+mod bitmap_proof;
+#[cfg(feature = "alloc")]
+mod builder;
+#[cfg(feature = "alloc")]
+pub mod codec;
+mod error;
 pub mod merkle_tree;
+mod proof_ref;
 
-pub use crate::merkle_tree::{MerkleProof, MerkleTree, CBMT};
+#[cfg(feature = "blake2b")]
+pub mod blake2b;
+#[cfg(feature = "keccak256")]
+pub mod keccak256;
+#[cfg(feature = "molecule")]
+pub mod molecule;
+#[cfg(feature = "alloc")]
+mod node_proof;
+#[cfg(feature = "rayon")]
+mod parallel;
+#[cfg(feature = "alloc")]
+mod partial_tree;
+#[cfg(feature = "alloc")]
+mod range_proof;
+#[cfg(feature = "serde")]
+pub mod serde_hex;
+#[cfg(feature = "serde")]
+mod serde_impl;
+#[cfg(feature = "sha256")]
+pub mod sha256;
+#[cfg(feature = "alloc")]
+mod sorted;
+mod streaming;
+
+#[cfg(feature = "alloc")]
+pub use crate::bitmap_proof::BitmapProof;
+#[cfg(feature = "alloc")]
+pub use crate::builder::CbmtBuilder;
+pub use crate::error::MerkleError;
+pub use crate::merkle_tree::CBMT;
+#[cfg(feature = "alloc")]
+pub use crate::merkle_tree::{MerkleProof, MerkleTree};
+#[cfg(feature = "alloc")]
+pub use crate::partial_tree::PartialMerkleTree;
+pub use crate::proof_ref::MerkleProofRef;
+#[cfg(feature = "alloc")]
+pub use crate::range_proof::RangeProof;
+#[cfg(feature = "alloc")]
+pub use crate::sorted::{AbsenceProof, SortedCbmt};
+
+/// A 32 bytes hash, the node type of the built-in merge implementations.
+pub type H256 = [u8; 32];
+
+/// Encodes a leaf count as the little endian number padded to 32 bytes, which
+/// the built-in merges mix into roots, the same as SSZ's `mix_in_length`.
+#[cfg(any(feature = "blake2b", feature = "sha256", feature = "keccak256"))]
+pub(crate) fn length_to_h256(leaves_count: u32) -> H256 {
+    let mut result = [0u8; 32];
+    result[..4].copy_from_slice(&leaves_count.to_le_bytes());
+    result
+}
 
 cfg_if::cfg_if! {
     if #[cfg(feature = "std")] {
         use std::collections;
         use std::vec;
-    } else {
+    } else if #[cfg(feature = "alloc")] {
         extern crate alloc;
         use alloc::collections;
         use alloc::vec;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::H256;
+    use crate::merkle_tree::Merge;
+    use crate::CBMT;
+    use core::ops::Range;
+    use proptest::collection::vec;
+    use proptest::num::i32;
+    use proptest::prelude::*;
+    use proptest::sample::subsequence;
+
+    /// Parses a `0x` prefixed hex string into a `H256`.
+    #[allow(dead_code)]
+    pub(crate) fn h256(hex: &str) -> H256 {
+        let hex = hex.trim_start_matches("0x");
+        let mut result = [0u8; 32];
+        for (i, byte) in result.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).unwrap();
+        }
+        result
+    }
+
+    pub(crate) struct MergeI32 {}
+
+    impl Merge for MergeI32 {
+        type Item = i32;
+        fn merge(left: &Self::Item, right: &Self::Item) -> Self::Item {
+            right.wrapping_sub(*left)
+        }
+    }
+
+    #[allow(dead_code)]
+    pub(crate) type CBMTI32 = CBMT<i32, MergeI32>;
+
+    /// Same as `MergeI32`, but leaves are hashed before they are put into the tree.
+    pub(crate) struct HashLeafMergeI32 {}
+
+    impl Merge for HashLeafMergeI32 {
+        type Item = i32;
+        fn merge(left: &Self::Item, right: &Self::Item) -> Self::Item {
+            right.wrapping_sub(*left)
+        }
+
+        fn hash_leaf(leaf: &Self::Item) -> Self::Item {
+            leaf.wrapping_mul(31).wrapping_add(7)
+        }
+    }
+
+    #[allow(dead_code)]
+    pub(crate) type HashLeafCBMTI32 = CBMT<i32, HashLeafMergeI32>;
+
+    /// A merge of 4 byte arrays, for the encodings of fixed size items.
+    pub(crate) struct MergeBytes {}
+
+    impl Merge for MergeBytes {
+        type Item = [u8; 4];
+        fn merge(left: &Self::Item, right: &Self::Item) -> Self::Item {
+            let mut result = [0u8; 4];
+            for (i, byte) in result.iter_mut().enumerate() {
+                *byte = left[i].wrapping_mul(3) ^ right[i];
+            }
+            result
+        }
+    }
+
+    #[allow(dead_code)]
+    pub(crate) type CBMTBytes = CBMT<[u8; 4], MergeBytes>;
+
+    /// Leaves and a non-empty subsequence of their indices, which are not all of
+    /// the leaves.
+    #[allow(dead_code)]
+    pub(crate) fn leaves_and_indices(
+        len: Range<usize>,
+    ) -> impl Strategy<Value = (Vec<i32>, Vec<u32>)> {
+        vec(i32::ANY, len).prop_flat_map(|leaves| {
+            let indices = subsequence(
+                (0..leaves.len() as u32).collect::<Vec<u32>>(),
+                1..leaves.len(),
+            );
+            (Just(leaves), indices)
+        })
+    }
+
+    /// Same as `leaves_and_indices`, but with two subsequences of the indices.
+    #[allow(dead_code)]
+    pub(crate) fn leaves_and_two_indices(
+        len: Range<usize>,
+    ) -> impl Strategy<Value = (Vec<i32>, Vec<u32>, Vec<u32>)> {
+        vec(i32::ANY, len).prop_flat_map(|leaves| {
+            let indices = (0..leaves.len() as u32).collect::<Vec<u32>>();
+            let len = 1..leaves.len();
+            (
+                Just(leaves),
+                subsequence(indices.clone(), len.clone()),
+                subsequence(indices, len),
+            )
+        })
+    }
+}
@@ -1,17 +1,54 @@
+#[cfg(feature = "alloc")]
+use crate::error::MerkleError;
+#[cfg(feature = "alloc")]
+use crate::proof_ref::{calculate_root, MerkleProofRef};
+#[cfg(feature = "alloc")]
 use crate::{collections::VecDeque, vec, vec::Vec};
+#[cfg(feature = "alloc")]
 use core::cmp::Reverse;
 use core::marker::PhantomData;
 
 pub trait Merge {
     type Item;
     fn merge(left: &Self::Item, right: &Self::Item) -> Self::Item;
+
+    /// Hashes a leaf before it is put into the tree, it is applied when building
+    /// trees and roots, and when calculating the root of a proof.
+    ///
+    /// The default implementation returns the leaf as is. Override it with a
+    /// hash distinct from `merge` (e.g. prefixing leaves and nodes differently,
+    /// like RFC 6962) so that an interior node can't be presented as a leaf.
+    fn hash_leaf(leaf: &Self::Item) -> Self::Item
+    where
+        Self::Item: Clone,
+    {
+        leaf.clone()
+    }
 }
 
+/// A `Merge` that can mix the number of leaves into the root, like SSZ's
+/// `mix_in_length`, so that the root commits to the leaf count and verifiers
+/// don't need to trust it separately.
+///
+/// Roots built with `build_merkle_root` are not affected, the count is only mixed
+/// in by the `*_with_length` methods.
+pub trait MixInLength: Merge {
+    /// Encodes the leaf count as a node.
+    fn hash_length(leaves_count: u32) -> Self::Item;
+
+    /// Returns `merge(root, hash_length(leaves_count))`.
+    fn mix_in_length(root: &Self::Item, leaves_count: u32) -> Self::Item {
+        Self::merge(root, &Self::hash_length(leaves_count))
+    }
+}
+
+#[cfg(feature = "alloc")]
 pub struct MerkleTree<T, M> {
-    nodes: Vec<T>,
-    merge: PhantomData<M>,
+    pub(crate) nodes: Vec<T>,
+    pub(crate) merge: PhantomData<M>,
 }
 
+#[cfg(feature = "alloc")]
 impl<T, M> MerkleTree<T, M>
 where
     T: Ord + Default + Clone,
@@ -19,49 +56,90 @@ where
 {
     /// `leaf_indices`: The indices of leaves
     pub fn build_proof(&self, leaf_indices: &[u32]) -> Option<MerkleProof<T, M>> {
-        if self.nodes.is_empty() || leaf_indices.is_empty() {
-            return None;
+        self.try_build_proof(leaf_indices).ok()
+    }
+
+    /// Same as `build_proof`, but returns the reason of the failure.
+    pub fn try_build_proof(&self, leaf_indices: &[u32]) -> Result<MerkleProof<T, M>, MerkleError> {
+        if self.nodes.is_empty() {
+            return Err(MerkleError::EmptyTree);
+        }
+        if leaf_indices.is_empty() {
+            return Err(MerkleError::EmptyIndices);
         }
 
         let leaves_count = ((self.nodes.len() >> 1) + 1) as u32;
+        if let Some(index) = leaf_indices.iter().find(|i| **i >= leaves_count) {
+            return Err(MerkleError::IndexOutOfRange { index: *index });
+        }
         let mut indices = leaf_indices
             .iter()
             .map(|i| leaves_count + i - 1)
             .collect::<Vec<_>>();
 
         indices.sort_by_key(|i| Reverse(*i));
-        if indices[0] >= (leaves_count << 1) - 1 {
-            return None;
+        if let Some(pair) = indices.windows(2).find(|pair| pair[0] == pair[1]) {
+            return Err(MerkleError::DuplicateIndex {
+                index: pair[0] + 1 - leaves_count,
+            });
+        }
+
+        let lemmas = collect_lemmas(&indices, |sibling| self.nodes[sibling as usize].clone());
+        indices.sort_by_key(|i| &self.nodes[*i as usize]);
+
+        Ok(MerkleProof {
+            indices,
+            lemmas,
+            merge: PhantomData,
+        })
+    }
+
+    /// Replaces the leaf at `leaf_index` and recalculates its ancestors.
+    pub fn update_leaf(&mut self, leaf_index: u32, leaf: T) -> Result<(), MerkleError> {
+        self.update_leaves(&[(leaf_index, leaf)])
+    }
+
+    /// Replaces the leaves and recalculates their ancestors, every ancestor is
+    /// merged only once. Nothing is changed if any index is invalid.
+    ///
+    /// `leaves`: pairs of leaf index and leaf
+    pub fn update_leaves(&mut self, leaves: &[(u32, T)]) -> Result<(), MerkleError> {
+        let leaves_count = ((self.nodes.len() + 1) >> 1) as u32;
+        if let Some((index, _)) = leaves.iter().find(|(i, _)| *i >= leaves_count) {
+            return Err(MerkleError::IndexOutOfRange { index: *index });
+        }
+
+        let mut indices = leaves
+            .iter()
+            .map(|(i, _)| leaves_count + i - 1)
+            .collect::<Vec<_>>();
+        indices.sort_by_key(|i| Reverse(*i));
+        if let Some(pair) = indices.windows(2).find(|pair| pair[0] == pair[1]) {
+            return Err(MerkleError::DuplicateIndex {
+                index: pair[0] + 1 - leaves_count,
+            });
         }
 
-        let mut lemmas = Vec::new();
-        let mut queue: VecDeque<u32> = indices.clone().into();
+        for (i, leaf) in leaves {
+            self.nodes[(leaves_count + i - 1) as usize] = M::hash_leaf(leaf);
+        }
 
+        let mut queue: VecDeque<u32> = indices.into();
         while let Some(index) = queue.pop_front() {
             if index == 0 {
-                assert!(queue.is_empty());
                 break;
             }
-            let sibling = index.sibling();
-            if Some(&sibling) == queue.front() {
-                queue.pop_front();
-            } else {
-                lemmas.push(self.nodes[sibling as usize].clone());
-            }
-
             let parent = index.parent();
-            if parent != 0 {
+            if queue.back() != Some(&parent) {
+                self.nodes[parent as usize] = M::merge(
+                    &self.nodes[((parent << 1) + 1) as usize],
+                    &self.nodes[((parent << 1) + 2) as usize],
+                );
                 queue.push_back(parent);
             }
         }
 
-        indices.sort_by_key(|i| &self.nodes[*i as usize]);
-
-        Some(MerkleProof {
-            indices,
-            lemmas,
-            merge: PhantomData,
-        })
+        Ok(())
     }
 
     pub fn root(&self) -> T {
@@ -75,14 +153,113 @@ where
     pub fn nodes(&self) -> &[T] {
         &self.nodes
     }
+
+    /// The node index of the leaf at `leaf_index`, `None` if it's out of range.
+    pub fn leaf_index_to_node(&self, leaf_index: u32) -> Option<u32> {
+        leaf_index_to_node(self.leaves_count(), leaf_index)
+    }
+
+    /// The leaf index of the node at `node_index`, `None` if it's not a leaf.
+    pub fn node_to_leaf_index(&self, node_index: u32) -> Option<u32> {
+        node_to_leaf_index(self.leaves_count(), node_index)
+    }
+
+    pub fn leaves_count(&self) -> u32 {
+        ((self.nodes.len() + 1) >> 1) as u32
+    }
+
+    /// The indices of the left and right children, `None` if the node is a leaf
+    /// or out of range.
+    pub fn children(&self, index: u32) -> Option<(u32, u32)> {
+        if index < self.leaves_count().saturating_sub(1) {
+            Some(((index << 1) + 1, (index << 1) + 2))
+        } else {
+            None
+        }
+    }
+
+    /// `None` if the node is the root or out of range.
+    pub fn parent(&self, index: u32) -> Option<u32> {
+        if index != 0 && (index as usize) < self.nodes.len() {
+            Some(index.parent())
+        } else {
+            None
+        }
+    }
+
+    /// `None` if the node is the root or out of range.
+    pub fn sibling(&self, index: u32) -> Option<u32> {
+        if index != 0 && (index as usize) < self.nodes.len() {
+            Some(index.sibling())
+        } else {
+            None
+        }
+    }
+
+    /// The number of edges from the root to the node, `None` if it's out of range.
+    pub fn depth(&self, index: u32) -> Option<u32> {
+        if (index as usize) < self.nodes.len() {
+            Some(31 - (index + 1).leading_zeros())
+        } else {
+            None
+        }
+    }
+
+    pub fn is_leaf(&self, index: u32) -> bool {
+        self.node_to_leaf_index(index).is_some()
+    }
+
+    /// The node indices from the leaf at `leaf_index` up to the root, both
+    /// included, `None` if the leaf is out of range.
+    pub fn path_to_root(&self, leaf_index: u32) -> Option<Vec<u32>> {
+        let mut index = self.leaf_index_to_node(leaf_index)?;
+        let mut path = vec![index];
+        while index != 0 {
+            index = index.parent();
+            path.push(index);
+        }
+        Some(path)
+    }
+
+    /// The number of levels, which is the depth of the deepest leaf plus one.
+    pub fn height(&self) -> u32 {
+        match self.nodes.len() {
+            0 => 0,
+            len => self.depth(len as u32 - 1).unwrap() + 1,
+        }
+    }
+
+    /// Iterates the nodes level by level from the root, the nodes at depth `d`
+    /// are `2^d - 1..2^(d + 1) - 1`. Leaves are in the last two levels when the
+    /// number of leaves is not a power of two.
+    pub fn levels(&self) -> impl Iterator<Item = &[T]> {
+        (0..self.height()).map(move |depth| {
+            let start = (1usize << depth) - 1;
+            &self.nodes[start..((start << 1) + 1).min(self.nodes.len())]
+        })
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T, M> MerkleTree<T, M>
+where
+    T: Ord + Default + Clone,
+    M: MixInLength<Item = T>,
+{
+    /// The root with the number of leaves mixed in.
+    pub fn root_with_length(&self) -> T {
+        M::mix_in_length(&self.root(), self.leaves_count())
+    }
 }
 
+#[cfg(feature = "alloc")]
 pub struct MerkleProof<T, M> {
-    indices: Vec<u32>,
-    lemmas: Vec<T>,
-    merge: PhantomData<M>,
+    pub(crate) indices: Vec<u32>,
+    pub(crate) lemmas: Vec<T>,
+    pub(crate) merge: PhantomData<M>,
 }
 
+#[cfg(feature = "alloc")]
 impl<T, M> MerkleProof<T, M>
 where
     T: Ord + Default + Clone,
@@ -96,54 +273,170 @@ where
         }
     }
 
+    /// Builds a proof of a tree with `leaves_count` leaves from leaf positions
+    /// instead of node indices, `None` if any position is out of range.
+    ///
+    /// The indices keep the order of `leaf_positions`. `root` and `verify` expect
+    /// them in the order of the hashes of the leaves, as `MerkleTree::build_proof`
+    /// returns, while `root_with_indexed_leaves` accepts any order.
+    pub fn from_leaf_positions(
+        leaf_positions: &[u32],
+        leaves_count: u32,
+        lemmas: Vec<T>,
+    ) -> Option<Self> {
+        let indices = leaf_positions
+            .iter()
+            .map(|position| leaf_index_to_node(leaves_count, *position))
+            .collect::<Option<Vec<_>>>()?;
+        Some(Self::new(indices, lemmas))
+    }
+
+    /// The leaf positions of `indices` in a tree with `leaves_count` leaves, in
+    /// the same order, `None` if any index is not a leaf.
+    pub fn leaf_positions(&self, leaves_count: u32) -> Option<Vec<u32>> {
+        self.indices
+            .iter()
+            .map(|index| node_to_leaf_index(leaves_count, *index))
+            .collect()
+    }
+
     pub fn root(&self, leaves: &[T]) -> Option<T> {
-        if leaves.len() != self.indices.len() || leaves.is_empty() {
-            return None;
+        self.try_root(leaves).ok()
+    }
+
+    /// Same as `root`, but returns the reason of the failure.
+    pub fn try_root(&self, leaves: &[T]) -> Result<T, MerkleError> {
+        if self.indices.is_empty() {
+            return Err(MerkleError::EmptyProof);
+        }
+        if leaves.len() != self.indices.len() {
+            return Err(MerkleError::LeafCountMismatch {
+                expected: self.indices.len(),
+                actual: leaves.len(),
+            });
         }
 
-        let mut leaves = leaves.to_vec();
+        calculate_root::<T, M>(&mut self.pair_leaves(leaves), &self.lemmas)
+    }
+
+    /// Pairs the hashed leaves with `indices`, both in the order of the hashes.
+    pub(crate) fn pair_leaves(&self, leaves: &[T]) -> Vec<(u32, T)> {
+        let mut leaves = leaves.iter().map(M::hash_leaf).collect::<Vec<_>>();
         leaves.sort();
 
-        let mut pre = self
-            .indices
+        self.indices.iter().copied().zip(leaves).collect()
+    }
+
+    /// Calculates the root from leaves paired with their indices in the proof,
+    /// instead of relying on the order of `indices`.
+    ///
+    /// `indices` are ordered by the hashes of the leaves, so when the same leaf
+    /// appears at different positions, the order can't tell which position each
+    /// leaf is at. Pairing the leaves with the indices explicitly avoids that, and
+    /// also accepts proofs whose `indices` are in any order.
+    ///
+    /// `indexed_leaves`: pairs of node index, as in `indices`, and leaf
+    pub fn root_with_indexed_leaves(&self, indexed_leaves: &[(u32, T)]) -> Option<T> {
+        self.try_root_with_indexed_leaves(indexed_leaves).ok()
+    }
+
+    /// Same as `root_with_indexed_leaves`, but returns the reason of the failure.
+    pub fn try_root_with_indexed_leaves(
+        &self,
+        indexed_leaves: &[(u32, T)],
+    ) -> Result<T, MerkleError> {
+        if self.indices.is_empty() {
+            return Err(MerkleError::EmptyProof);
+        }
+        if indexed_leaves.len() != self.indices.len() {
+            return Err(MerkleError::LeafCountMismatch {
+                expected: self.indices.len(),
+                actual: indexed_leaves.len(),
+            });
+        }
+
+        let mut indices = self.indices.clone();
+        indices.sort_unstable();
+        if let Some((index, _)) = indexed_leaves
             .iter()
-            .zip(leaves.into_iter())
-            .map(|(i, l)| (*i, l))
+            .find(|(index, _)| indices.binary_search(index).is_err())
+        {
+            return Err(MerkleError::IndexNotInProof { index: *index });
+        }
+
+        let mut pre = indexed_leaves
+            .iter()
+            .map(|(i, l)| (*i, M::hash_leaf(l)))
             .collect::<Vec<_>>();
-        pre.sort_by_key(|i| Reverse(i.0));
+        calculate_root::<T, M>(&mut pre, &self.lemmas)
+    }
 
-        let mut queue: VecDeque<(u32, T)> = pre.into();
-        let mut lemmas_iter = self.lemmas.iter();
+    pub fn verify_with_indexed_leaves(&self, root: &T, indexed_leaves: &[(u32, T)]) -> bool {
+        match self.root_with_indexed_leaves(indexed_leaves) {
+            Some(r) => &r == root,
+            _ => false,
+        }
+    }
 
-        while let Some((index, node)) = queue.pop_front() {
-            if index == 0 {
-                // ensure that all lemmas and leaves are consumed
-                if lemmas_iter.next().is_none() && queue.is_empty() {
-                    return Some(node);
-                } else {
-                    return None;
-                }
-            }
+    pub fn verify(&self, root: &T, leaves: &[T]) -> bool {
+        match self.root(leaves) {
+            Some(r) => &r == root,
+            _ => false,
+        }
+    }
 
-            if let Some(sibling) = match queue.front() {
-                Some((front, _)) if *front == index.sibling() => queue.pop_front().map(|i| i.1),
-                _ => lemmas_iter.next().cloned(),
-            } {
-                let parent_node = if index.is_left() {
-                    M::merge(&node, &sibling)
-                } else {
-                    M::merge(&sibling, &node)
-                };
+    /// Calculates the root of a tree with `leaves_count` leaves.
+    ///
+    /// `root` accepts any indices as long as the merges reach the root, this
+    /// also checks that every index is a leaf of the tree, and that the number of
+    /// lemmas is the same as `MerkleTree::build_proof` produces.
+    pub fn root_with_leaf_count(&self, leaves: &[T], leaves_count: u32) -> Option<T> {
+        self.try_root_with_leaf_count(leaves, leaves_count).ok()
+    }
 
-                queue.push_back((index.parent(), parent_node));
-            }
+    /// Same as `root_with_leaf_count`, but returns the reason of the failure.
+    pub fn try_root_with_leaf_count(
+        &self,
+        leaves: &[T],
+        leaves_count: u32,
+    ) -> Result<T, MerkleError> {
+        if leaves_count == 0 {
+            return Err(MerkleError::EmptyTree);
+        }
+        if self.indices.is_empty() {
+            return Err(MerkleError::EmptyProof);
+        }
+        if let Some(index) = self
+            .indices
+            .iter()
+            .find(|i| node_to_leaf_index(leaves_count, **i).is_none())
+        {
+            return Err(MerkleError::NodeIndexOutOfRange { index: *index });
         }
 
-        None
+        let mut indices = self.indices.clone();
+        indices.sort_by_key(|i| Reverse(*i));
+        if let Some(pair) = indices.windows(2).find(|pair| pair[0] == pair[1]) {
+            return Err(MerkleError::DuplicateNodeIndex { index: pair[0] });
+        }
+
+        let lemmas_count = collect_lemmas(&indices, |_| ()).len();
+        if self.lemmas.len() < lemmas_count {
+            return Err(MerkleError::LemmaCountMismatch);
+        }
+        if self.lemmas.len() > lemmas_count {
+            return Err(MerkleError::UnconsumedLemmas {
+                count: self.lemmas.len() - lemmas_count,
+            });
+        }
+
+        self.try_root(leaves)
     }
 
-    pub fn verify(&self, root: &T, leaves: &[T]) -> bool {
-        match self.root(leaves) {
+    /// Verifies the proof against `root` as `verify` does, with the same checks
+    /// against `leaves_count` as `root_with_leaf_count`.
+    pub fn verify_with_leaf_count(&self, root: &T, leaves: &[T], leaves_count: u32) -> bool {
+        match self.root_with_leaf_count(leaves, leaves_count) {
             Some(r) => &r == root,
             _ => false,
         }
@@ -156,6 +449,40 @@ where
     pub fn lemmas(&self) -> &[T] {
         &self.lemmas
     }
+
+    /// Borrows the proof as a `MerkleProofRef`, which verifies without allocation.
+    pub fn as_proof_ref(&self) -> MerkleProofRef<'_, T, M> {
+        MerkleProofRef::new(&self.indices, &self.lemmas)
+    }
+}
+
+#[cfg(feature = "alloc")]
+impl<T, M> MerkleProof<T, M>
+where
+    T: Ord + Default + Clone,
+    M: MixInLength<Item = T>,
+{
+    /// Calculates the root with `leaves_count` mixed in, to be compared with
+    /// `CBMT::build_merkle_root_with_length`.
+    pub fn root_with_length(&self, leaves: &[T], leaves_count: u32) -> Option<T> {
+        self.try_root_with_length(leaves, leaves_count).ok()
+    }
+
+    /// Same as `root_with_length`, but returns the reason of the failure.
+    ///
+    /// The proof is checked against `leaves_count` as `try_root_with_leaf_count`
+    /// does.
+    pub fn try_root_with_length(&self, leaves: &[T], leaves_count: u32) -> Result<T, MerkleError> {
+        self.try_root_with_leaf_count(leaves, leaves_count)
+            .map(|root| M::mix_in_length(&root, leaves_count))
+    }
+
+    pub fn verify_with_length(&self, root: &T, leaves: &[T], leaves_count: u32) -> bool {
+        match self.root_with_length(leaves, leaves_count) {
+            Some(r) => &r == root,
+            _ => false,
+        }
+    }
 }
 
 #[derive(Default)]
@@ -164,6 +491,7 @@ pub struct CBMT<T, M> {
     merge: PhantomData<M>,
 }
 
+#[cfg(feature = "alloc")]
 impl<T, M> CBMT<T, M>
 where
     T: Ord + Default + Clone,
@@ -178,10 +506,10 @@ where
 
         let mut iter = leaves.rchunks_exact(2);
         while let Some([leaf1, leaf2]) = iter.next() {
-            queue.push_back(M::merge(leaf1, leaf2))
+            queue.push_back(M::merge(&M::hash_leaf(leaf1), &M::hash_leaf(leaf2)))
         }
         if let [leaf] = iter.remainder() {
-            queue.push_front(leaf.clone())
+            queue.push_front(M::hash_leaf(leaf))
         }
 
         while queue.len() > 1 {
@@ -197,7 +525,7 @@ where
         let len = leaves.len();
         if len > 0 {
             let mut nodes = vec![T::default(); len - 1];
-            nodes.extend_from_slice(leaves);
+            nodes.extend(leaves.iter().map(M::hash_leaf));
 
             (0..len - 1)
                 .rev()
@@ -215,37 +543,97 @@ where
         }
     }
 
+    /// The root with the number of leaves mixed in, it differs from
+    /// `build_merkle_root` even for the same leaves.
+    pub fn build_merkle_root_with_length(leaves: &[T]) -> T
+    where
+        M: MixInLength,
+    {
+        M::mix_in_length(&Self::build_merkle_root(leaves), leaves.len() as u32)
+    }
+
     pub fn build_merkle_proof(leaves: &[T], leaf_indices: &[u32]) -> Option<MerkleProof<T, M>> {
         Self::build_merkle_tree(leaves).build_proof(leaf_indices)
     }
 
     /// retrieve that a proof points to leaves of a tree, returning `None` if the proof indices is empty or out of bounds
     pub fn retrieve_leaves(leaves: &[T], proof: &MerkleProof<T, M>) -> Option<Vec<T>> {
-        if leaves.is_empty() || proof.indices().is_empty() {
-            return None;
+        Self::try_retrieve_leaves(leaves, proof).ok()
+    }
+
+    /// Same as `retrieve_leaves`, but returns the reason of the failure.
+    pub fn try_retrieve_leaves(
+        leaves: &[T],
+        proof: &MerkleProof<T, M>,
+    ) -> Result<Vec<T>, MerkleError> {
+        if leaves.is_empty() {
+            return Err(MerkleError::EmptyTree);
+        }
+        if proof.indices().is_empty() {
+            return Err(MerkleError::EmptyProof);
         }
 
         let leaves_count = leaves.len() as u32;
-        let valid_indices_range = leaves_count - 1..(leaves_count << 1) - 1;
-        if proof
+        proof
             .indices()
             .iter()
-            .all(|index| valid_indices_range.contains(index))
-        {
-            Some(
-                proof
-                    .indices()
-                    .iter()
-                    .map(|index| leaves[(index + 1 - leaves_count) as usize].clone())
-                    .collect(),
-            )
+            .map(|index| {
+                node_to_leaf_index(leaves_count, *index)
+                    .map(|position| leaves[position as usize].clone())
+                    .ok_or(MerkleError::NodeIndexOutOfRange { index: *index })
+            })
+            .collect()
+    }
+}
+
+/// Collects the lemmas of a proof of `indices`, which are in descending order.
+///
+/// `node`: returns the node at an index
+#[cfg(feature = "alloc")]
+pub(crate) fn collect_lemmas<T, F>(indices: &[u32], mut node: F) -> Vec<T>
+where
+    F: FnMut(u32) -> T,
+{
+    let mut lemmas = Vec::new();
+    let mut queue: VecDeque<u32> = indices.iter().copied().collect();
+
+    while let Some(index) = queue.pop_front() {
+        if index == 0 {
+            break;
+        }
+        let sibling = index.sibling();
+        if Some(&sibling) == queue.front() {
+            queue.pop_front();
         } else {
-            None
+            lemmas.push(node(sibling));
+        }
+
+        let parent = index.parent();
+        if parent != 0 {
+            queue.push_back(parent);
         }
     }
+
+    lemmas
+}
+
+#[cfg(feature = "alloc")]
+pub(crate) fn leaf_index_to_node(leaves_count: u32, leaf_index: u32) -> Option<u32> {
+    if leaf_index < leaves_count {
+        (leaves_count - 1).checked_add(leaf_index)
+    } else {
+        None
+    }
 }
 
-trait TreeIndex {
+#[cfg(feature = "alloc")]
+pub(crate) fn node_to_leaf_index(leaves_count: u32, node_index: u32) -> Option<u32> {
+    node_index
+        .checked_sub(leaves_count.checked_sub(1)?)
+        .filter(|leaf_index| *leaf_index < leaves_count)
+}
+
+pub(crate) trait TreeIndex {
     fn sibling(&self) -> Self;
     fn parent(&self) -> Self;
     fn is_left(&self) -> bool;
@@ -283,24 +671,23 @@ impl_tree_index!(usize);
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::tests::{leaves_and_indices, HashLeafCBMTI32, HashLeafMergeI32, MergeI32, CBMTI32};
     use proptest::collection::vec;
     use proptest::num::i32;
     use proptest::prelude::*;
     use proptest::proptest;
     use proptest::sample::subsequence;
 
-    struct MergeI32 {}
-
-    impl Merge for MergeI32 {
-        type Item = i32;
-        fn merge(left: &Self::Item, right: &Self::Item) -> Self::Item {
-            right.wrapping_sub(*left)
+    impl MixInLength for MergeI32 {
+        fn hash_length(leaves_count: u32) -> Self::Item {
+            leaves_count as i32
         }
     }
 
-    type CBMTI32 = CBMT<i32, MergeI32>;
     type CBMTI32Proof = MerkleProof<i32, MergeI32>;
 
+    type HashLeafCBMTI32Proof = MerkleProof<i32, HashLeafMergeI32>;
+
     #[test]
     fn build_empty() {
         let leaves = vec![];
@@ -415,6 +802,54 @@ mod tests {
         }
     }
 
+    #[test]
+    fn build_with_hash_leaf() {
+        let leaves = vec![2i32, 3, 5, 7, 11];
+        let tree = HashLeafCBMTI32::build_merkle_tree(&leaves);
+        assert_eq!(
+            vec![117, -55, 62, 124, 69, 100, 162, 224, 348],
+            tree.nodes()
+        );
+        assert_eq!(117, HashLeafCBMTI32::build_merkle_root(&leaves));
+
+        let proof = tree.build_proof(&[1, 3]).unwrap();
+        assert!(proof.verify(&tree.root(), &[3, 7]));
+    }
+
+    #[test]
+    fn interior_node_as_leaf() {
+        let leaves = vec![2i32, 3, 5, 7, 11];
+
+        // without leaf hashing, node 1 can be presented as a leaf
+        let tree = CBMTI32::build_merkle_tree(&leaves);
+        let forged_proof = CBMTI32Proof::new(vec![1], vec![tree.nodes()[2]]);
+        assert!(forged_proof.verify(&tree.root(), &[tree.nodes()[1]]));
+
+        let tree = HashLeafCBMTI32::build_merkle_tree(&leaves);
+        let forged_proof = HashLeafCBMTI32Proof::new(vec![1], vec![tree.nodes()[2]]);
+        assert!(!forged_proof.verify(&tree.root(), &[tree.nodes()[1]]));
+    }
+
+    fn _hash_leaf_tree_root_is_same_as_proof_root(leaves: Vec<i32>, leaf_indices: Vec<u32>) {
+        let proof_leaves = leaf_indices
+            .iter()
+            .map(|i| leaves[*i as usize])
+            .collect::<Vec<_>>();
+
+        let proof = HashLeafCBMTI32::build_merkle_proof(&leaves, &leaf_indices).unwrap();
+        let root = HashLeafCBMTI32::build_merkle_root(&leaves);
+        assert_eq!(root, proof.root(&proof_leaves).unwrap());
+    }
+
+    proptest! {
+        #[test]
+        fn hash_leaf_tree_root_is_same_as_proof_root(input in vec(i32::ANY,  2..1000)
+            .prop_flat_map(|leaves| (Just(leaves.clone()), subsequence((0..leaves.len() as u32).collect::<Vec<u32>>(), 1..leaves.len())))
+        ) {
+            _hash_leaf_tree_root_is_same_as_proof_root(input.0, input.1);
+        }
+    }
+
     #[test]
     fn verify_retrieve_leaves() {
         let leaves = vec![2i32, 3, 5, 7, 11, 13];
@@ -435,5 +870,445 @@ mod tests {
 
         proof.indices = vec![11];
         assert_eq!(None, CBMTI32::retrieve_leaves(&leaves, &proof));
+        assert_eq!(
+            Err(MerkleError::NodeIndexOutOfRange { index: 11 }),
+            CBMTI32::try_retrieve_leaves(&leaves, &proof)
+        );
+    }
+
+    #[test]
+    fn build_proof_errors() {
+        let tree = CBMTI32::build_merkle_tree(&[]);
+        assert_eq!(
+            Some(MerkleError::EmptyTree),
+            tree.try_build_proof(&[0]).err()
+        );
+
+        let tree = CBMTI32::build_merkle_tree(&[2i32, 3, 5, 7, 11]);
+        assert_eq!(
+            Some(MerkleError::EmptyIndices),
+            tree.try_build_proof(&[]).err()
+        );
+        assert_eq!(
+            Some(MerkleError::IndexOutOfRange { index: 5 }),
+            tree.try_build_proof(&[0, 5]).err()
+        );
+        // used to overflow
+        assert_eq!(
+            Some(MerkleError::IndexOutOfRange { index: u32::MAX }),
+            tree.try_build_proof(&[u32::MAX]).err()
+        );
+        assert!(tree.build_proof(&[u32::MAX]).is_none());
+        // used to hit an assertion
+        assert_eq!(
+            Some(MerkleError::DuplicateIndex { index: 3 }),
+            tree.try_build_proof(&[3, 1, 3]).err()
+        );
+        assert!(tree.build_proof(&[0, 0]).is_none());
+        let tree = CBMTI32::build_merkle_tree(&[2i32]);
+        assert!(tree.build_proof(&[0, 0]).is_none());
+    }
+
+    #[test]
+    fn proof_root_errors() {
+        let leaves = vec![2i32, 3, 5, 7, 11, 13];
+        let proof = CBMTI32::build_merkle_proof(&leaves, &[0, 5]).unwrap();
+        assert_eq!(Ok(1), proof.try_root(&[2, 13]));
+
+        assert_eq!(
+            Some(MerkleError::EmptyProof),
+            CBMTI32Proof::new(vec![], vec![]).try_root(&[]).err()
+        );
+        assert_eq!(
+            Some(MerkleError::LeafCountMismatch {
+                expected: 2,
+                actual: 1
+            }),
+            proof.try_root(&[2]).err()
+        );
+
+        let duplicate = CBMTI32Proof::new(vec![5, 5], proof.lemmas().to_vec());
+        assert_eq!(
+            Some(MerkleError::DuplicateNodeIndex { index: 5 }),
+            duplicate.try_root(&[2, 13]).err()
+        );
+
+        let missing_lemma = CBMTI32Proof::new(proof.indices().to_vec(), vec![11, 3]);
+        assert_eq!(
+            Some(MerkleError::LemmaCountMismatch),
+            missing_lemma.try_root(&[2, 13]).err()
+        );
+
+        let extra_lemma = CBMTI32Proof::new(proof.indices().to_vec(), vec![11, 3, 2, 1]);
+        assert_eq!(
+            Some(MerkleError::UnconsumedLemmas { count: 1 }),
+            extra_lemma.try_root(&[2, 13]).err()
+        );
+
+        // node 1 is the ancestor of node 3
+        let tree = CBMTI32::build_merkle_tree(&leaves);
+        let ancestor = CBMTI32Proof::new(
+            vec![1, 3],
+            vec![tree.nodes()[4], tree.nodes()[2], tree.nodes()[2]],
+        );
+        assert_eq!(
+            Some(MerkleError::UnconsumedNodes { count: 1 }),
+            ancestor.try_root(&[tree.nodes()[1], tree.nodes()[3]]).err()
+        );
+    }
+
+    #[test]
+    fn root_with_indexed_leaves() {
+        let leaves = vec![13i32, 3, 2, 7, 2, 5];
+        let tree = CBMTI32::build_merkle_tree(&leaves);
+        let root = tree.root();
+        let proof = tree.build_proof(&[0, 2, 4]).unwrap();
+        assert!(proof.verify(&root, &[13, 2, 2]));
+
+        let indexed_leaves = vec![(5, 13), (7, 2), (9, 2)];
+        assert_eq!(Some(root), proof.root_with_indexed_leaves(&indexed_leaves));
+        assert!(proof.verify_with_indexed_leaves(&root, &indexed_leaves));
+        assert!(!proof.verify_with_indexed_leaves(&root, &[(5, 2), (7, 2), (9, 13)]));
+
+        // indices ordered by position instead of hash
+        let mut indices = proof.indices().to_vec();
+        indices.sort_unstable();
+        let proof = CBMTI32Proof::new(indices, proof.lemmas().to_vec());
+        assert!(!proof.verify(&root, &[13, 2, 2]));
+        assert!(proof.verify_with_indexed_leaves(&root, &indexed_leaves));
+
+        assert_eq!(
+            Err(MerkleError::IndexNotInProof { index: 6 }),
+            proof.try_root_with_indexed_leaves(&[(5, 13), (6, 3), (9, 2)])
+        );
+        assert_eq!(
+            Err(MerkleError::LeafCountMismatch {
+                expected: 3,
+                actual: 2
+            }),
+            proof.try_root_with_indexed_leaves(&[(5, 13), (7, 2)])
+        );
+        assert_eq!(
+            Err(MerkleError::DuplicateNodeIndex { index: 5 }),
+            proof.try_root_with_indexed_leaves(&[(5, 13), (5, 13), (9, 2)])
+        );
+    }
+
+    fn _tree_root_is_same_as_indexed_proof_root(leaves: Vec<i32>, leaf_indices: Vec<u32>) {
+        let leaves_count = leaves.len() as u32;
+        let indexed_leaves = leaf_indices
+            .iter()
+            .map(|i| (leaves_count + i - 1, leaves[*i as usize]))
+            .collect::<Vec<_>>();
+
+        let proof = CBMTI32::build_merkle_proof(&leaves, &leaf_indices).unwrap();
+        let root = CBMTI32::build_merkle_root(&leaves);
+        assert_eq!(
+            root,
+            proof.root_with_indexed_leaves(&indexed_leaves).unwrap()
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn tree_root_is_same_as_indexed_proof_root(input in vec(0..4i32, 2..1000)
+            .prop_flat_map(|leaves| (Just(leaves.clone()), subsequence((0..leaves.len() as u32).collect::<Vec<u32>>(), 1..leaves.len())))
+        ) {
+            _tree_root_is_same_as_indexed_proof_root(input.0, input.1);
+        }
+    }
+
+    #[test]
+    fn update_leaf() {
+        let mut tree = CBMTI32::build_merkle_tree(&[2i32, 3, 5, 7, 11]);
+        tree.update_leaf(2, 13).unwrap();
+        assert_eq!(
+            CBMTI32::build_merkle_tree(&[2i32, 3, 13, 7, 11]).nodes(),
+            tree.nodes()
+        );
+
+        assert_eq!(
+            Err(MerkleError::IndexOutOfRange { index: 5 }),
+            tree.update_leaves(&[(0, 1), (5, 1)])
+        );
+        assert_eq!(
+            Err(MerkleError::DuplicateIndex { index: 1 }),
+            tree.update_leaves(&[(1, 1), (1, 2)])
+        );
+        assert_eq!(
+            CBMTI32::build_merkle_tree(&[2i32, 3, 13, 7, 11]).nodes(),
+            tree.nodes()
+        );
+
+        let mut tree = CBMTI32::build_merkle_tree(&[]);
+        assert_eq!(
+            Err(MerkleError::IndexOutOfRange { index: 0 }),
+            tree.update_leaf(0, 1)
+        );
+
+        let mut tree = HashLeafCBMTI32::build_merkle_tree(&[2i32]);
+        tree.update_leaf(0, 3).unwrap();
+        assert_eq!(HashLeafCBMTI32::build_merkle_root(&[3]), tree.root());
+    }
+
+    fn _update_leaves_is_same_as_rebuild(leaves: Vec<i32>, updates: Vec<(u32, i32)>) {
+        let mut tree = HashLeafCBMTI32::build_merkle_tree(&leaves);
+        tree.update_leaves(&updates).unwrap();
+
+        let mut updated_leaves = leaves;
+        for (i, leaf) in updates {
+            updated_leaves[i as usize] = leaf;
+        }
+        assert_eq!(
+            HashLeafCBMTI32::build_merkle_tree(&updated_leaves).nodes(),
+            tree.nodes()
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn update_leaves_is_same_as_rebuild(input in vec(i32::ANY, 1..500)
+            .prop_flat_map(|leaves| {
+                let indices = subsequence((0..leaves.len() as u32).collect::<Vec<u32>>(), 0..=leaves.len());
+                (Just(leaves), indices, vec(i32::ANY, 500))
+            })
+        ) {
+            let (leaves, indices, values) = input;
+            _update_leaves_is_same_as_rebuild(leaves, indices.into_iter().zip(values).collect());
+        }
+    }
+
+    #[test]
+    fn verify_with_leaf_count() {
+        let leaves = vec![2i32, 3, 5, 7, 11];
+        let tree = CBMTI32::build_merkle_tree(&leaves);
+        let root = tree.root();
+
+        let proof = tree.build_proof(&[0, 3]).unwrap();
+        assert_eq!(&[4, 7], proof.indices());
+        assert!(proof.verify_with_leaf_count(&root, &[2, 7], 5));
+        assert_eq!(
+            Err(MerkleError::NodeIndexOutOfRange { index: 7 }),
+            proof.try_root_with_leaf_count(&[2, 7], 4)
+        );
+        assert_eq!(
+            Err(MerkleError::EmptyTree),
+            proof.try_root_with_leaf_count(&[2, 7], 0)
+        );
+
+        // node 1 presented as a leaf closes the arithmetic, but it's not a leaf
+        let forged_proof = CBMTI32Proof::new(vec![1], vec![tree.nodes()[2]]);
+        assert!(forged_proof.verify(&root, &[tree.nodes()[1]]));
+        assert!(!forged_proof.verify_with_leaf_count(&root, &[tree.nodes()[1]], 5));
+
+        let mut lemmas = proof.lemmas().to_vec();
+        lemmas.push(0);
+        assert_eq!(
+            Err(MerkleError::UnconsumedLemmas { count: 1 }),
+            CBMTI32Proof::new(vec![4, 7], lemmas).try_root_with_leaf_count(&[2, 7], 5)
+        );
+        assert_eq!(
+            Err(MerkleError::LemmaCountMismatch),
+            CBMTI32Proof::new(vec![4, 7], proof.lemmas()[1..].to_vec())
+                .try_root_with_leaf_count(&[2, 7], 5)
+        );
+        assert_eq!(
+            Err(MerkleError::DuplicateNodeIndex { index: 7 }),
+            CBMTI32Proof::new(vec![7, 7], proof.lemmas().to_vec())
+                .try_root_with_leaf_count(&[7, 7], 5)
+        );
+    }
+
+    fn _verify_with_leaf_count(leaves: Vec<i32>, leaf_indices: Vec<u32>) {
+        let leaves_count = leaves.len() as u32;
+        let proof_leaves = leaf_indices
+            .iter()
+            .map(|i| leaves[*i as usize])
+            .collect::<Vec<_>>();
+
+        let proof = CBMTI32::build_merkle_proof(&leaves, &leaf_indices).unwrap();
+        let root = CBMTI32::build_merkle_root(&leaves);
+        assert!(proof.verify_with_leaf_count(&root, &proof_leaves, leaves_count));
+
+        // the parent of a leaf is never a leaf
+        let mut indices = proof.indices().to_vec();
+        let parent = (indices[0] - 1) >> 1;
+        indices[0] = parent;
+        assert_eq!(
+            Err(MerkleError::NodeIndexOutOfRange { index: parent }),
+            CBMTI32Proof::new(indices, proof.lemmas().to_vec())
+                .try_root_with_leaf_count(&proof_leaves, leaves_count)
+        );
+
+        // not all leaves are proven, so there is at least one lemma
+        assert_eq!(
+            Err(MerkleError::LemmaCountMismatch),
+            CBMTI32Proof::new(proof.indices().to_vec(), proof.lemmas()[1..].to_vec())
+                .try_root_with_leaf_count(&proof_leaves, leaves_count)
+        );
+
+        let mut lemmas = proof.lemmas().to_vec();
+        lemmas.push(0);
+        assert_eq!(
+            Err(MerkleError::UnconsumedLemmas { count: 1 }),
+            CBMTI32Proof::new(proof.indices().to_vec(), lemmas)
+                .try_root_with_leaf_count(&proof_leaves, leaves_count)
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn proof_verifies_with_leaf_count(input in leaves_and_indices(2..1000)) {
+            _verify_with_leaf_count(input.0, input.1);
+        }
+    }
+
+    #[test]
+    fn leaf_positions() {
+        let leaves = vec![2i32, 3, 5, 7, 11];
+        let tree = CBMTI32::build_merkle_tree(&leaves);
+        assert_eq!(Some(4), tree.leaf_index_to_node(0));
+        assert_eq!(Some(8), tree.leaf_index_to_node(4));
+        assert_eq!(None, tree.leaf_index_to_node(5));
+        assert_eq!(Some(0), tree.node_to_leaf_index(4));
+        assert_eq!(Some(4), tree.node_to_leaf_index(8));
+        assert_eq!(None, tree.node_to_leaf_index(3));
+        assert_eq!(None, tree.node_to_leaf_index(9));
+
+        let empty_tree = CBMTI32::build_merkle_tree(&[]);
+        assert_eq!(None, empty_tree.leaf_index_to_node(0));
+        assert_eq!(None, empty_tree.node_to_leaf_index(0));
+
+        let proof = tree.build_proof(&[3, 0]).unwrap();
+        assert_eq!(Some(vec![0, 3]), proof.leaf_positions(5));
+        assert_eq!(None, proof.leaf_positions(4));
+
+        let rebuilt_proof =
+            CBMTI32Proof::from_leaf_positions(&[0, 3], 5, proof.lemmas().to_vec()).unwrap();
+        assert_eq!(proof.indices(), rebuilt_proof.indices());
+        assert!(rebuilt_proof.verify(&tree.root(), &[2, 7]));
+        assert!(CBMTI32Proof::from_leaf_positions(&[0, 5], 5, vec![]).is_none());
+    }
+
+    #[test]
+    fn navigate() {
+        let tree = CBMTI32::build_merkle_tree(&[2, 3, 5, 7, 11]);
+        assert_eq!(5, tree.leaves_count());
+
+        assert_eq!(Some((1, 2)), tree.children(0));
+        assert_eq!(Some((7, 8)), tree.children(3));
+        assert_eq!(None, tree.children(4));
+        assert_eq!(None, tree.children(9));
+        assert_eq!(None, tree.children(u32::MAX));
+
+        assert_eq!(None, tree.parent(0));
+        assert_eq!(Some(3), tree.parent(8));
+        assert_eq!(None, tree.parent(9));
+        assert_eq!(None, tree.sibling(0));
+        assert_eq!(Some(8), tree.sibling(7));
+        assert_eq!(Some(3), tree.sibling(4));
+        assert_eq!(None, tree.sibling(9));
+
+        assert_eq!(Some(0), tree.depth(0));
+        assert_eq!(Some(1), tree.depth(2));
+        assert_eq!(Some(2), tree.depth(3));
+        assert_eq!(Some(3), tree.depth(8));
+        assert_eq!(None, tree.depth(9));
+
+        assert!(!tree.is_leaf(3));
+        assert!(tree.is_leaf(4));
+        assert!(tree.is_leaf(8));
+        assert!(!tree.is_leaf(9));
+
+        assert_eq!(Some(vec![4, 1, 0]), tree.path_to_root(0));
+        assert_eq!(Some(vec![8, 3, 1, 0]), tree.path_to_root(4));
+        assert_eq!(None, tree.path_to_root(5));
+
+        assert_eq!(4, tree.height());
+        let nodes = tree.nodes();
+        assert_eq!(
+            vec![&nodes[0..1], &nodes[1..3], &nodes[3..7], &nodes[7..9]],
+            tree.levels().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn navigate_small_trees() {
+        let tree = CBMTI32::build_merkle_tree(&[]);
+        assert_eq!(0, tree.height());
+        assert_eq!(0, tree.levels().count());
+        assert_eq!(None, tree.depth(0));
+        assert!(!tree.is_leaf(0));
+
+        let tree = CBMTI32::build_merkle_tree(&[2]);
+        assert_eq!(1, tree.height());
+        assert_eq!(None, tree.children(0));
+        assert!(tree.is_leaf(0));
+        assert_eq!(Some(vec![0]), tree.path_to_root(0));
+    }
+
+    #[test]
+    fn build_root_with_length() {
+        // the same root without the leaf count mixed in
+        assert_eq!(
+            CBMTI32::build_merkle_root(&[5]),
+            CBMTI32::build_merkle_root(&[0, 5])
+        );
+        assert_eq!(1 - 5, CBMTI32::build_merkle_root_with_length(&[5]));
+        assert_eq!(2 - 5, CBMTI32::build_merkle_root_with_length(&[0, 5]));
+        assert_eq!(0, CBMTI32::build_merkle_root_with_length(&[]));
+
+        let leaves = vec![2i32, 3, 5, 7, 11];
+        assert_eq!(
+            CBMTI32::build_merkle_root_with_length(&leaves),
+            CBMTI32::build_merkle_tree(&leaves).root_with_length()
+        );
+    }
+
+    #[test]
+    fn verify_with_length() {
+        let leaves = vec![2i32, 3, 5, 7, 11];
+        let tree = CBMTI32::build_merkle_tree(&leaves);
+        let root = tree.root_with_length();
+
+        let proof = tree.build_proof(&[0, 3]).unwrap();
+        assert!(proof.verify_with_length(&root, &[2, 7], 5));
+        assert!(!proof.verify_with_length(&root, &[2, 7], 6));
+        assert!(!proof.verify(&root, &[2, 7]));
+        assert_eq!(
+            Err(MerkleError::EmptyTree),
+            proof.try_root_with_length(&[2, 7], 0)
+        );
+
+        // node 1 can't be presented as a leaf, it's not a leaf of a tree with 5 leaves
+        let forged_proof = CBMTI32Proof::new(vec![1], vec![tree.nodes()[2]]);
+        assert_eq!(
+            Err(MerkleError::NodeIndexOutOfRange { index: 1 }),
+            forged_proof.try_root_with_length(&[tree.nodes()[1]], 5)
+        );
+    }
+
+    fn _proof_root_with_length_is_same_as_root_with_length(
+        leaves: Vec<i32>,
+        leaf_indices: Vec<u32>,
+    ) {
+        let proof_leaves = leaf_indices
+            .iter()
+            .map(|i| leaves[*i as usize])
+            .collect::<Vec<_>>();
+        let proof = CBMTI32::build_merkle_proof(&leaves, &leaf_indices).unwrap();
+        assert_eq!(
+            Some(CBMTI32::build_merkle_root_with_length(&leaves)),
+            proof.root_with_length(&proof_leaves, leaves.len() as u32)
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn proof_root_with_length_is_same_as_root_with_length(input in vec(i32::ANY,  2..1000)
+            .prop_flat_map(|leaves| (Just(leaves.clone()), subsequence((0..leaves.len() as u32).collect::<Vec<u32>>(), 1..leaves.len())))
+        ) {
+            _proof_root_with_length_is_same_as_root_with_length(input.0, input.1);
+        }
     }
 }
@@ -0,0 +1,269 @@
+//! Molecule codec of `MerkleProof<[u8; 32], M>`, compatible with the proofs in
+//! CKB's serialization such as the `proof` of `get_transaction_proof`.
+//!
+//! The schema is in `schemas/merkle_proof.mol`:
+//!
+//! ```text
+//! vector Uint32Vec <Uint32>;
+//! vector Byte32Vec <Byte32>;
+//!
+//! table MerkleProof {
+//!     indices:                Uint32Vec,
+//!     lemmas:                 Byte32Vec,
+//! }
+//! ```
+
+use crate::merkle_tree::{Merge, MerkleProof};
+use crate::vec::Vec;
+use crate::H256;
+use core::convert::TryInto;
+use core::fmt;
+
+const NUMBER_SIZE: usize = 4;
+const FIELD_COUNT: usize = 2;
+const HEADER_SIZE: usize = NUMBER_SIZE * (FIELD_COUNT + 1);
+
+/// Errors of the molecule verification, the same as the ones reported by the
+/// molecule crate in strict mode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MoleculeError {
+    /// The total size in the header doesn't match the length of the data.
+    TotalSizeNotMatch,
+    /// The data is too short to contain the header.
+    HeaderIsBroken,
+    /// The field offsets are unaligned, out of bounds or not ascending.
+    OffsetsNotMatch,
+    /// The table doesn't have exactly 2 fields.
+    FieldCountNotMatch(usize),
+    /// The size of a vector doesn't match its item count.
+    ItemCountNotMatch,
+}
+
+impl fmt::Display for MoleculeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MoleculeError::TotalSizeNotMatch => write!(f, "total size doesn't match"),
+            MoleculeError::HeaderIsBroken => write!(f, "header is broken"),
+            MoleculeError::OffsetsNotMatch => write!(f, "offsets don't match"),
+            MoleculeError::FieldCountNotMatch(count) => {
+                write!(f, "expect {} fields, got {}", FIELD_COUNT, count)
+            }
+            MoleculeError::ItemCountNotMatch => write!(f, "item count doesn't match"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for MoleculeError {}
+
+fn read_number(bytes: &[u8]) -> usize {
+    u32::from_le_bytes(bytes[..NUMBER_SIZE].try_into().expect("checked length")) as usize
+}
+
+fn push_number(buf: &mut Vec<u8>, number: usize) {
+    buf.extend_from_slice(&(number as u32).to_le_bytes());
+}
+
+/// Verifies a fixvec of `item_size` items, returning the bytes of the items.
+fn verify_fixvec(bytes: &[u8], item_size: usize) -> Result<&[u8], MoleculeError> {
+    if bytes.len() < NUMBER_SIZE {
+        return Err(MoleculeError::HeaderIsBroken);
+    }
+    let item_count = read_number(bytes);
+    if item_count
+        .checked_mul(item_size)
+        .map(|size| size + NUMBER_SIZE)
+        != Some(bytes.len())
+    {
+        return Err(MoleculeError::ItemCountNotMatch);
+    }
+    Ok(&bytes[NUMBER_SIZE..])
+}
+
+impl<M> MerkleProof<H256, M>
+where
+    M: Merge<Item = H256>,
+{
+    /// Serializes the proof as a molecule `MerkleProof` table.
+    pub fn to_molecule(&self) -> Vec<u8> {
+        let indices_size = NUMBER_SIZE + self.indices.len() * NUMBER_SIZE;
+        let lemmas_size = NUMBER_SIZE + self.lemmas.len() * 32;
+        let total_size = HEADER_SIZE + indices_size + lemmas_size;
+
+        let mut buf = Vec::with_capacity(total_size);
+        push_number(&mut buf, total_size);
+        push_number(&mut buf, HEADER_SIZE);
+        push_number(&mut buf, HEADER_SIZE + indices_size);
+
+        push_number(&mut buf, self.indices.len());
+        for index in &self.indices {
+            buf.extend_from_slice(&index.to_le_bytes());
+        }
+        push_number(&mut buf, self.lemmas.len());
+        for lemma in &self.lemmas {
+            buf.extend_from_slice(lemma);
+        }
+        buf
+    }
+
+    /// Deserializes a molecule `MerkleProof` table, the data is verified in
+    /// strict mode, so tables with extra fields are rejected.
+    // `usize::is_multiple_of` is only stable since Rust 1.87.
+    #[allow(clippy::manual_is_multiple_of)]
+    pub fn from_molecule(bytes: &[u8]) -> Result<Self, MoleculeError> {
+        if bytes.len() < NUMBER_SIZE {
+            return Err(MoleculeError::HeaderIsBroken);
+        }
+        if read_number(bytes) != bytes.len() {
+            return Err(MoleculeError::TotalSizeNotMatch);
+        }
+        if bytes.len() == NUMBER_SIZE {
+            return Err(MoleculeError::FieldCountNotMatch(0));
+        }
+        if bytes.len() < NUMBER_SIZE * 2 {
+            return Err(MoleculeError::HeaderIsBroken);
+        }
+        let first_offset = read_number(&bytes[NUMBER_SIZE..]);
+        if first_offset % NUMBER_SIZE != 0 || first_offset < NUMBER_SIZE * 2 {
+            return Err(MoleculeError::OffsetsNotMatch);
+        }
+        let field_count = first_offset / NUMBER_SIZE - 1;
+        if field_count != FIELD_COUNT {
+            return Err(MoleculeError::FieldCountNotMatch(field_count));
+        }
+        if bytes.len() < HEADER_SIZE {
+            return Err(MoleculeError::HeaderIsBroken);
+        }
+        let second_offset = read_number(&bytes[NUMBER_SIZE * 2..]);
+        if first_offset > second_offset || second_offset > bytes.len() {
+            return Err(MoleculeError::OffsetsNotMatch);
+        }
+
+        let indices = verify_fixvec(&bytes[first_offset..second_offset], NUMBER_SIZE)?
+            .chunks_exact(NUMBER_SIZE)
+            .map(|chunk| read_number(chunk) as u32)
+            .collect();
+        let lemmas = verify_fixvec(&bytes[second_offset..], 32)?
+            .chunks_exact(32)
+            .map(|chunk| chunk.try_into().expect("checked length"))
+            .collect();
+
+        Ok(MerkleProof::new(indices, lemmas))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::h256;
+    use crate::CBMT;
+
+    struct XorMerge {}
+
+    impl Merge for XorMerge {
+        type Item = H256;
+        fn merge(left: &Self::Item, right: &Self::Item) -> Self::Item {
+            let mut result = *left;
+            for (i, byte) in result.iter_mut().enumerate() {
+                *byte = byte.rotate_left(1) ^ right[i];
+            }
+            result
+        }
+    }
+
+    type XorProof = MerkleProof<H256, XorMerge>;
+
+    fn from_hex(hex: &str) -> Vec<u8> {
+        (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    // encoded by `ckb_types::packed::MerkleProof`
+    const PROOF: &str = "5c0000000c0000001800000002000000050000000a0000000200000000000000000000000000000000000000000000000000000000000000000000010000000000000000000000000000000000000000000000000000000000000002";
+    const EMPTY_PROOF: &str = "140000000c000000100000000000000000000000";
+
+    #[test]
+    fn encode_proof() {
+        let proof = XorProof::new(
+            vec![5, 10],
+            vec![
+                h256("0x0000000000000000000000000000000000000000000000000000000000000001"),
+                h256("0x0000000000000000000000000000000000000000000000000000000000000002"),
+            ],
+        );
+        assert_eq!(from_hex(PROOF), proof.to_molecule());
+
+        let decoded = XorProof::from_molecule(&from_hex(PROOF)).unwrap();
+        assert_eq!(proof.indices(), decoded.indices());
+        assert_eq!(proof.lemmas(), decoded.lemmas());
+
+        let empty_proof = XorProof::new(vec![], vec![]);
+        assert_eq!(from_hex(EMPTY_PROOF), empty_proof.to_molecule());
+        assert!(XorProof::from_molecule(&from_hex(EMPTY_PROOF))
+            .unwrap()
+            .indices()
+            .is_empty());
+    }
+
+    #[test]
+    fn decode_invalid_proof() {
+        let proof = from_hex(PROOF);
+
+        assert_eq!(
+            Err(MoleculeError::HeaderIsBroken),
+            XorProof::from_molecule(&[0, 0]).map(|_| ())
+        );
+        assert_eq!(
+            Err(MoleculeError::TotalSizeNotMatch),
+            XorProof::from_molecule(&proof[..proof.len() - 1]).map(|_| ())
+        );
+        assert_eq!(
+            Err(MoleculeError::FieldCountNotMatch(0)),
+            XorProof::from_molecule(&[4, 0, 0, 0]).map(|_| ())
+        );
+
+        // a table with 3 fields
+        let mut extra_field = from_hex(EMPTY_PROOF);
+        extra_field.splice(4..12, from_hex("1000000014000000"));
+        extra_field.splice(12..12, from_hex("18000000"));
+        extra_field[0] = 0x18;
+        assert_eq!(
+            Err(MoleculeError::FieldCountNotMatch(3)),
+            XorProof::from_molecule(&extra_field).map(|_| ())
+        );
+
+        let mut unaligned = proof.clone();
+        unaligned[4] = 0x0d;
+        assert_eq!(
+            Err(MoleculeError::OffsetsNotMatch),
+            XorProof::from_molecule(&unaligned).map(|_| ())
+        );
+
+        let mut descending = proof.clone();
+        descending[8] = 0x08;
+        assert_eq!(
+            Err(MoleculeError::OffsetsNotMatch),
+            XorProof::from_molecule(&descending).map(|_| ())
+        );
+
+        let mut wrong_count = proof;
+        wrong_count[12] = 3;
+        assert_eq!(
+            Err(MoleculeError::ItemCountNotMatch),
+            XorProof::from_molecule(&wrong_count).map(|_| ())
+        );
+    }
+
+    #[test]
+    fn molecule_round_trip() {
+        let leaves = (0u8..7).map(|i| [i; 32]).collect::<Vec<_>>();
+        let proof = CBMT::<H256, XorMerge>::build_merkle_proof(&leaves, &[1, 4, 6]).unwrap();
+        let decoded = XorProof::from_molecule(&proof.to_molecule()).unwrap();
+        assert!(decoded.verify(
+            &CBMT::<H256, XorMerge>::build_merkle_root(&leaves),
+            &[leaves[1], leaves[4], leaves[6]]
+        ));
+    }
+}
@@ -0,0 +1,281 @@
+use crate::collections::{BTreeSet, BinaryHeap};
+use crate::error::MerkleError;
+use crate::merkle_tree::{Merge, MerkleProof, MerkleTree, TreeIndex};
+use crate::vec::Vec;
+use core::cmp::Reverse;
+
+impl<T, M> MerkleTree<T, M>
+where
+    T: Ord + Default + Clone,
+    M: Merge<Item = T>,
+{
+    /// Builds a proof of nodes at any depth, e.g. the root of a subtree, instead of
+    /// leaves. A node can't be proved together with its ancestors.
+    ///
+    /// The proof is verified with `MerkleProof::root_with_nodes`.
+    ///
+    /// `node_indices`: The indices of nodes
+    pub fn build_node_proof(&self, node_indices: &[u32]) -> Option<MerkleProof<T, M>> {
+        self.try_build_node_proof(node_indices).ok()
+    }
+
+    /// Same as `build_node_proof`, but returns the reason of the failure.
+    pub fn try_build_node_proof(
+        &self,
+        node_indices: &[u32],
+    ) -> Result<MerkleProof<T, M>, MerkleError> {
+        if self.nodes.is_empty() {
+            return Err(MerkleError::EmptyTree);
+        }
+        if node_indices.is_empty() {
+            return Err(MerkleError::EmptyIndices);
+        }
+        if let Some(index) = node_indices
+            .iter()
+            .find(|i| **i as usize >= self.nodes.len())
+        {
+            return Err(MerkleError::NodeIndexOutOfRange { index: *index });
+        }
+
+        let mut indices = node_indices.to_vec();
+        check_node_indices(&mut indices)?;
+
+        // nodes are at different depths, so they are merged from the greatest index
+        // with a heap instead of a queue
+        let mut lemmas = Vec::new();
+        let mut heap: BinaryHeap<u32> = indices.iter().copied().collect();
+        while let Some(index) = heap.pop() {
+            if index == 0 {
+                break;
+            }
+            let sibling = index.sibling();
+            if heap.peek() == Some(&sibling) {
+                heap.pop();
+            } else {
+                lemmas.push(self.nodes[sibling as usize].clone());
+            }
+            heap.push(index.parent());
+        }
+
+        indices.sort_by_key(|i| &self.nodes[*i as usize]);
+        Ok(MerkleProof::new(indices, lemmas))
+    }
+}
+
+impl<T, M> MerkleProof<T, M>
+where
+    T: Ord + Default + Clone,
+    M: Merge<Item = T>,
+{
+    /// Calculates the root of a proof built by `MerkleTree::build_node_proof`.
+    ///
+    /// `nodes`: the proved nodes as in `MerkleTree::nodes`, leaves are not hashed
+    /// again by `Merge::hash_leaf`
+    pub fn root_with_nodes(&self, nodes: &[T]) -> Option<T> {
+        self.try_root_with_nodes(nodes).ok()
+    }
+
+    /// Same as `root_with_nodes`, but returns the reason of the failure.
+    pub fn try_root_with_nodes(&self, nodes: &[T]) -> Result<T, MerkleError> {
+        if self.indices.is_empty() {
+            return Err(MerkleError::EmptyProof);
+        }
+        if nodes.len() != self.indices.len() {
+            return Err(MerkleError::LeafCountMismatch {
+                expected: self.indices.len(),
+                actual: nodes.len(),
+            });
+        }
+        check_node_indices(&mut self.indices.clone())?;
+
+        let mut nodes = nodes.to_vec();
+        nodes.sort();
+        let mut heap: BinaryHeap<(u32, T)> = self.indices.iter().copied().zip(nodes).collect();
+        let mut lemmas_iter = self.lemmas.iter();
+
+        while let Some((index, node)) = heap.pop() {
+            if index == 0 {
+                // the root has the least index, all other nodes are merged before it
+                let unconsumed_lemmas = lemmas_iter.len();
+                if unconsumed_lemmas > 0 {
+                    return Err(MerkleError::UnconsumedLemmas {
+                        count: unconsumed_lemmas,
+                    });
+                }
+                return Ok(node);
+            }
+
+            let sibling = if heap.peek().map(|(i, _)| *i) == Some(index.sibling()) {
+                heap.pop().map(|(_, sibling)| sibling).unwrap()
+            } else {
+                lemmas_iter
+                    .next()
+                    .cloned()
+                    .ok_or(MerkleError::LemmaCountMismatch)?
+            };
+
+            let parent_node = if index.is_left() {
+                M::merge(&node, &sibling)
+            } else {
+                M::merge(&sibling, &node)
+            };
+            heap.push((index.parent(), parent_node));
+        }
+
+        unreachable!("the heap is not empty until the root is reached")
+    }
+
+    pub fn verify_with_nodes(&self, root: &T, nodes: &[T]) -> bool {
+        match self.root_with_nodes(nodes) {
+            Some(r) => &r == root,
+            _ => false,
+        }
+    }
+}
+
+/// Sorts the node indices in descending order, and checks that no index appears
+/// twice or is an ancestor of another.
+fn check_node_indices(indices: &mut [u32]) -> Result<(), MerkleError> {
+    indices.sort_by_key(|i| Reverse(*i));
+    if let Some(pair) = indices.windows(2).find(|pair| pair[0] == pair[1]) {
+        return Err(MerkleError::DuplicateNodeIndex { index: pair[0] });
+    }
+
+    let set = indices.iter().copied().collect::<BTreeSet<_>>();
+    for descendant in indices.iter() {
+        let mut index = *descendant;
+        while index != 0 {
+            index = index.parent();
+            if set.contains(&index) {
+                return Err(MerkleError::OverlappingIndices {
+                    ancestor: index,
+                    descendant: *descendant,
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{leaves_and_indices, MergeI32, CBMTI32};
+    use proptest::collection::vec;
+    use proptest::num::i32;
+    use proptest::prelude::*;
+    use proptest::proptest;
+    use proptest::sample::subsequence;
+
+    #[test]
+    fn build_node_proof() {
+        let tree = CBMTI32::build_merkle_tree(&[2, 3, 5, 7, 11, 13, 17]);
+        let nodes = tree.nodes();
+        let root = tree.root();
+
+        let proof = tree.build_node_proof(&[1]).unwrap();
+        assert_eq!(&[1], proof.indices());
+        assert_eq!(&[nodes[2]], proof.lemmas());
+        assert!(proof.verify_with_nodes(&root, &[nodes[1]]));
+        assert!(!proof.verify_with_nodes(&root, &[nodes[2]]));
+
+        // a leaf and an interior node at different depths
+        let proof = tree.build_node_proof(&[11, 1]).unwrap();
+        assert_eq!(&[nodes[12], nodes[6]], proof.lemmas());
+        assert!(proof.verify_with_nodes(&root, &[nodes[1], nodes[11]]));
+
+        let proof = tree.build_node_proof(&[0]).unwrap();
+        assert!(proof.lemmas().is_empty());
+        assert!(proof.verify_with_nodes(&root, &[root]));
+    }
+
+    #[test]
+    fn build_node_proof_errors() {
+        let tree = CBMTI32::build_merkle_tree(&[2, 3, 5, 7, 11, 13, 17]);
+        assert_eq!(
+            Some(MerkleError::NodeIndexOutOfRange { index: 13 }),
+            tree.try_build_node_proof(&[13]).err()
+        );
+        assert_eq!(
+            Some(MerkleError::DuplicateNodeIndex { index: 3 }),
+            tree.try_build_node_proof(&[3, 3]).err()
+        );
+        assert_eq!(
+            Some(MerkleError::OverlappingIndices {
+                ancestor: 1,
+                descendant: 8
+            }),
+            tree.try_build_node_proof(&[1, 8]).err()
+        );
+        assert_eq!(
+            Some(MerkleError::EmptyIndices),
+            tree.try_build_node_proof(&[]).err()
+        );
+
+        let nodes = tree.nodes();
+        let proof = MerkleProof::<i32, MergeI32>::new(vec![3, 1], vec![nodes[4], nodes[2]]);
+        assert_eq!(
+            Some(MerkleError::OverlappingIndices {
+                ancestor: 1,
+                descendant: 3
+            }),
+            proof.try_root_with_nodes(&[nodes[3], nodes[1]]).err()
+        );
+    }
+
+    fn _node_proof_root_is_same_as_tree_root(leaves: Vec<i32>, node_indices: Vec<u32>) {
+        let tree = CBMTI32::build_merkle_tree(&leaves);
+        let set = node_indices.iter().copied().collect::<BTreeSet<_>>();
+        // drop the nodes whose ancestors are selected
+        let node_indices = node_indices
+            .into_iter()
+            .filter(|i| {
+                let mut index = *i;
+                while index != 0 {
+                    index = index.parent();
+                    if set.contains(&index) {
+                        return false;
+                    }
+                }
+                true
+            })
+            .collect::<Vec<_>>();
+
+        let proof = tree.build_node_proof(&node_indices).unwrap();
+        let proof_nodes = node_indices
+            .iter()
+            .map(|i| tree.nodes()[*i as usize])
+            .collect::<Vec<_>>();
+        assert_eq!(Some(tree.root()), proof.root_with_nodes(&proof_nodes));
+    }
+
+    fn _leaf_node_proof_is_same_as_built_proof(leaves: Vec<i32>, leaf_indices: Vec<u32>) {
+        let tree = CBMTI32::build_merkle_tree(&leaves);
+        let node_indices = leaf_indices
+            .iter()
+            .map(|i| tree.leaf_index_to_node(*i).unwrap())
+            .collect::<Vec<_>>();
+
+        let proof = tree.build_node_proof(&node_indices).unwrap();
+        let expected = tree.build_proof(&leaf_indices).unwrap();
+        assert_eq!(expected.indices(), proof.indices());
+        assert_eq!(expected.lemmas(), proof.lemmas());
+    }
+
+    proptest! {
+        #[test]
+        fn node_proof_root_is_same_as_tree_root(input in vec(i32::ANY,  2..500)
+            .prop_flat_map(|leaves| {
+                let nodes_count = (leaves.len() << 1) - 1;
+                (Just(leaves), subsequence((0..nodes_count as u32).collect::<Vec<u32>>(), 1..nodes_count))
+            })
+        ) {
+            _node_proof_root_is_same_as_tree_root(input.0, input.1);
+        }
+
+        #[test]
+        fn leaf_node_proof_is_same_as_built_proof(input in leaves_and_indices(2..500)) {
+            _leaf_node_proof_is_same_as_built_proof(input.0, input.1);
+        }
+    }
+}
@@ -0,0 +1,77 @@
+//! Parallel tree construction, enabled by the `rayon` feature.
+
+use crate::merkle_tree::{Merge, MerkleTree, CBMT};
+use core::marker::PhantomData;
+use rayon::prelude::*;
+
+impl<T, M> CBMT<T, M>
+where
+    T: Ord + Default + Clone + Send + Sync,
+    M: Merge<Item = T>,
+{
+    /// Same as `build_merkle_tree`, but the leaves and the nodes of each level are
+    /// hashed in parallel. The nodes of a level only depend on the level below, and
+    /// are stored contiguously, so every level is split from the levels below it.
+    pub fn build_merkle_tree_par(leaves: &[T]) -> MerkleTree<T, M> {
+        let len = leaves.len();
+        let mut nodes = Vec::with_capacity((len << 1).saturating_sub(1));
+        if len > 0 {
+            nodes.resize(len - 1, T::default());
+            nodes.par_extend(leaves.par_iter().map(M::hash_leaf));
+
+            // nodes `2^d - 1..2^(d + 1) - 1` are at depth `d`, internal nodes are `0..len - 1`
+            let mut level_start = (len.next_power_of_two() >> 1).saturating_sub(1);
+            loop {
+                let level_end = ((level_start << 1) + 1).min(len - 1);
+                let (parents, children) = nodes.split_at_mut((level_start << 1) + 1);
+                let children_start = (level_start << 1) + 1;
+                parents[level_start..level_end]
+                    .par_iter_mut()
+                    .enumerate()
+                    .for_each(|(offset, node)| {
+                        let left = ((level_start + offset) << 1) + 1 - children_start;
+                        *node = M::merge(&children[left], &children[left + 1]);
+                    });
+
+                if level_start == 0 {
+                    break;
+                }
+                level_start >>= 1;
+            }
+        }
+
+        MerkleTree {
+            nodes,
+            merge: PhantomData,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::HashLeafCBMTI32;
+    use proptest::collection::vec;
+    use proptest::num::i32;
+    use proptest::proptest;
+
+    #[test]
+    fn build_par() {
+        for len in 0..70 {
+            let leaves = (0..len).collect::<Vec<i32>>();
+            assert_eq!(
+                HashLeafCBMTI32::build_merkle_tree(&leaves).nodes(),
+                HashLeafCBMTI32::build_merkle_tree_par(&leaves).nodes()
+            );
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn build_par_is_same_as_build(leaves in vec(i32::ANY, 0..5000)) {
+            assert_eq!(
+                HashLeafCBMTI32::build_merkle_tree(&leaves).nodes(),
+                HashLeafCBMTI32::build_merkle_tree_par(&leaves).nodes()
+            );
+        }
+    }
+}
@@ -0,0 +1,241 @@
+use crate::collections::BTreeMap;
+use crate::error::MerkleError;
+use crate::merkle_tree::{
+    collect_lemmas, leaf_index_to_node, node_to_leaf_index, Merge, MerkleProof,
+};
+use crate::vec::Vec;
+use core::cmp::Reverse;
+use core::marker::PhantomData;
+
+/// A sparse tree of the nodes learned from proofs of the same root, which can
+/// build proofs of the leaves whose paths are known, e.g. on light clients.
+pub struct PartialMerkleTree<T, M> {
+    root: T,
+    leaves_count: u32,
+    nodes: BTreeMap<u32, T>,
+    merge: PhantomData<M>,
+}
+
+impl<T, M> PartialMerkleTree<T, M>
+where
+    T: Ord + Default + Clone,
+    M: Merge<Item = T>,
+{
+    /// Creates an empty tree, proofs are only accepted if they are of `root` and
+    /// of a tree with `leaves_count` leaves.
+    pub fn new(root: T, leaves_count: u32) -> Self {
+        PartialMerkleTree {
+            root,
+            leaves_count,
+            nodes: BTreeMap::new(),
+            merge: PhantomData,
+        }
+    }
+
+    /// Verifies the proof and stores the nodes calculated on the way to the root.
+    /// Nothing is stored if the proof is invalid, or conflicts with the known
+    /// nodes.
+    ///
+    /// `leaves`: the leaves of the proof, as passed to `MerkleProof::root`
+    pub fn insert_proof(
+        &mut self,
+        proof: &MerkleProof<T, M>,
+        leaves: &[T],
+    ) -> Result<(), MerkleError> {
+        if let Some(index) = proof
+            .indices()
+            .iter()
+            .find(|i| node_to_leaf_index(self.leaves_count, **i).is_none())
+        {
+            return Err(MerkleError::NodeIndexOutOfRange { index: *index });
+        }
+
+        let nodes = proof.try_compute_nodes(leaves)?;
+        if nodes.get(&0) != Some(&self.root) {
+            return Err(MerkleError::NodeMismatch { index: 0 });
+        }
+        if let Some((index, _)) = nodes
+            .iter()
+            .find(|(index, node)| matches!(self.nodes.get(index), Some(known) if known != *node))
+        {
+            return Err(MerkleError::NodeMismatch { index: *index });
+        }
+
+        self.nodes.extend(nodes);
+        Ok(())
+    }
+
+    /// `leaf_indices`: The indices of leaves
+    pub fn build_proof(&self, leaf_indices: &[u32]) -> Option<MerkleProof<T, M>> {
+        self.try_build_proof(leaf_indices).ok()
+    }
+
+    /// Same as `build_proof`, but returns the reason of the failure, which is
+    /// `UnknownNode` if a leaf or a lemma has not been learned from any proof.
+    pub fn try_build_proof(&self, leaf_indices: &[u32]) -> Result<MerkleProof<T, M>, MerkleError> {
+        if self.leaves_count == 0 {
+            return Err(MerkleError::EmptyTree);
+        }
+        if leaf_indices.is_empty() {
+            return Err(MerkleError::EmptyIndices);
+        }
+
+        let mut indices = leaf_indices
+            .iter()
+            .map(|i| {
+                leaf_index_to_node(self.leaves_count, *i)
+                    .ok_or(MerkleError::IndexOutOfRange { index: *i })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        indices.sort_by_key(|i| Reverse(*i));
+        if let Some(pair) = indices.windows(2).find(|pair| pair[0] == pair[1]) {
+            return Err(MerkleError::DuplicateIndex {
+                index: pair[0] + 1 - self.leaves_count,
+            });
+        }
+        if let Some(index) = indices.iter().find(|i| !self.nodes.contains_key(i)) {
+            return Err(MerkleError::UnknownNode { index: *index });
+        }
+
+        let lemmas = collect_lemmas(&indices, |sibling| {
+            self.nodes
+                .get(&sibling)
+                .cloned()
+                .ok_or(MerkleError::UnknownNode { index: sibling })
+        })
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()?;
+        indices.sort_by_key(|i| &self.nodes[i]);
+
+        Ok(MerkleProof::new(indices, lemmas))
+    }
+
+    pub fn root(&self) -> &T {
+        &self.root
+    }
+
+    pub fn leaves_count(&self) -> u32 {
+        self.leaves_count
+    }
+
+    /// The known nodes by node index.
+    pub fn nodes(&self) -> &BTreeMap<u32, T> {
+        &self.nodes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{leaves_and_two_indices, MergeI32, CBMTI32};
+    use proptest::proptest;
+
+    type PartialMerkleTreeI32 = PartialMerkleTree<i32, MergeI32>;
+
+    #[test]
+    fn build_proof_from_partial_tree() {
+        let leaves = vec![2i32, 3, 5, 7, 11, 13, 17];
+        let tree = CBMTI32::build_merkle_tree(&leaves);
+        let mut partial_tree = PartialMerkleTreeI32::new(tree.root(), 7);
+        partial_tree
+            .insert_proof(&tree.build_proof(&[0]).unwrap(), &[2])
+            .unwrap();
+        partial_tree
+            .insert_proof(&tree.build_proof(&[5]).unwrap(), &[13])
+            .unwrap();
+
+        let proof = partial_tree.build_proof(&[5, 0]).unwrap();
+        let expected = tree.build_proof(&[5, 0]).unwrap();
+        assert_eq!(expected.indices(), proof.indices());
+        assert_eq!(expected.lemmas(), proof.lemmas());
+
+        // leaf 6 is the lemma of leaf 5
+        let proof = partial_tree.build_proof(&[6]).unwrap();
+        assert!(proof.verify(&tree.root(), &[17]));
+
+        assert_eq!(
+            Some(MerkleError::UnknownNode { index: 7 }),
+            partial_tree.try_build_proof(&[1]).err()
+        );
+        assert_eq!(
+            Some(MerkleError::UnknownNode { index: 7 }),
+            partial_tree.try_build_proof(&[1, 0]).err()
+        );
+        assert_eq!(
+            Some(MerkleError::IndexOutOfRange { index: 7 }),
+            partial_tree.try_build_proof(&[7]).err()
+        );
+        assert_eq!(
+            Some(MerkleError::DuplicateIndex { index: 0 }),
+            partial_tree.try_build_proof(&[0, 0]).err()
+        );
+    }
+
+    #[test]
+    fn insert_invalid_proof() {
+        let tree = CBMTI32::build_merkle_tree(&[2, 3, 5, 7, 11]);
+        let other_tree = CBMTI32::build_merkle_tree(&[2, 3, 5, 7, 12]);
+        let mut partial_tree = PartialMerkleTreeI32::new(tree.root(), 5);
+
+        assert_eq!(
+            Err(MerkleError::NodeMismatch { index: 0 }),
+            partial_tree.insert_proof(&other_tree.build_proof(&[4]).unwrap(), &[12])
+        );
+        assert_eq!(
+            Err(MerkleError::LemmaCountMismatch),
+            partial_tree.insert_proof(&MerkleProof::new(vec![4], vec![]), &[2])
+        );
+
+        // node 1 presented as a leaf
+        let forged_proof = MerkleProof::new(vec![1], vec![tree.nodes()[2]]);
+        assert!(forged_proof.verify(&tree.root(), &[tree.nodes()[1]]));
+        assert_eq!(
+            Err(MerkleError::NodeIndexOutOfRange { index: 1 }),
+            partial_tree.insert_proof(&forged_proof, &[tree.nodes()[1]])
+        );
+        assert!(partial_tree.nodes().is_empty());
+    }
+
+    fn _partial_tree_proof_is_same_as_built_proof(
+        leaves: Vec<i32>,
+        leaf_indices_a: Vec<u32>,
+        leaf_indices_b: Vec<u32>,
+    ) {
+        let tree = CBMTI32::build_merkle_tree(&leaves);
+        let proof_leaves = |leaf_indices: &[u32]| {
+            leaf_indices
+                .iter()
+                .map(|i| leaves[*i as usize])
+                .collect::<Vec<_>>()
+        };
+        let mut partial_tree = PartialMerkleTreeI32::new(tree.root(), leaves.len() as u32);
+        for leaf_indices in &[&leaf_indices_a, &leaf_indices_b] {
+            partial_tree
+                .insert_proof(
+                    &tree.build_proof(leaf_indices).unwrap(),
+                    &proof_leaves(leaf_indices),
+                )
+                .unwrap();
+        }
+
+        for (index, node) in partial_tree.nodes() {
+            assert_eq!(&tree.nodes()[*index as usize], node);
+        }
+
+        let mut leaf_indices = leaf_indices_a;
+        leaf_indices.extend(leaf_indices_b);
+        leaf_indices.sort_unstable();
+        leaf_indices.dedup();
+        let proof = partial_tree.build_proof(&leaf_indices).unwrap();
+        let expected = tree.build_proof(&leaf_indices).unwrap();
+        assert_eq!(expected.indices(), proof.indices());
+        assert_eq!(expected.lemmas(), proof.lemmas());
+    }
+
+    proptest! {
+        #[test]
+        fn partial_tree_proof_is_same_as_built_proof(input in leaves_and_two_indices(2..500)) {
+            _partial_tree_proof_is_same_as_built_proof(input.0, input.1, input.2);
+        }
+    }
+}
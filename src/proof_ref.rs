@@ -0,0 +1,242 @@
+use crate::error::MerkleError;
+use crate::merkle_tree::{Merge, TreeIndex};
+use core::cmp::Reverse;
+use core::marker::PhantomData;
+use core::mem;
+
+/// A merkle proof borrowing its indices and lemmas, which can be verified without
+/// allocation, e.g. in on-chain scripts.
+///
+/// The verification needs a scratch buffer with at least one slot per leaf, which
+/// is either supplied by the caller or allocated on the stack with a const
+/// generic capacity.
+pub struct MerkleProofRef<'a, T, M> {
+    indices: &'a [u32],
+    lemmas: &'a [T],
+    merge: PhantomData<M>,
+}
+
+impl<'a, T, M> MerkleProofRef<'a, T, M>
+where
+    T: Ord + Default + Clone,
+    M: Merge<Item = T>,
+{
+    pub fn new(indices: &'a [u32], lemmas: &'a [T]) -> Self {
+        MerkleProofRef {
+            indices,
+            lemmas,
+            merge: PhantomData,
+        }
+    }
+
+    pub fn indices(&self) -> &'a [u32] {
+        self.indices
+    }
+
+    pub fn lemmas(&self) -> &'a [T] {
+        self.lemmas
+    }
+
+    /// Same as `MerkleProof::try_root`, using `buffer` as the scratch space. The
+    /// buffer must have at least `leaves.len()` slots, its content is overwritten.
+    pub fn try_root_with_buffer(
+        &self,
+        leaves: &[T],
+        buffer: &mut [(u32, T)],
+    ) -> Result<T, MerkleError> {
+        if self.indices.is_empty() {
+            return Err(MerkleError::EmptyProof);
+        }
+        if leaves.len() != self.indices.len() {
+            return Err(MerkleError::LeafCountMismatch {
+                expected: self.indices.len(),
+                actual: leaves.len(),
+            });
+        }
+        if buffer.len() < leaves.len() {
+            return Err(MerkleError::BufferTooSmall {
+                required: leaves.len(),
+            });
+        }
+
+        let buffer = &mut buffer[..leaves.len()];
+        for (slot, leaf) in buffer.iter_mut().zip(leaves) {
+            slot.1 = M::hash_leaf(leaf);
+        }
+        // leaves are paired with the indices in the order of their hashes
+        buffer.sort_unstable_by(|a, b| a.1.cmp(&b.1));
+        for (slot, index) in buffer.iter_mut().zip(self.indices) {
+            slot.0 = *index;
+        }
+
+        calculate_root::<T, M>(buffer, self.lemmas)
+    }
+
+    pub fn root_with_buffer(&self, leaves: &[T], buffer: &mut [(u32, T)]) -> Option<T> {
+        self.try_root_with_buffer(leaves, buffer).ok()
+    }
+
+    pub fn verify_with_buffer(&self, root: &T, leaves: &[T], buffer: &mut [(u32, T)]) -> bool {
+        match self.root_with_buffer(leaves, buffer) {
+            Some(r) => &r == root,
+            _ => false,
+        }
+    }
+
+    /// Same as `try_root_with_buffer`, using a buffer of `N` slots on the stack.
+    pub fn try_root_with_capacity<const N: usize>(&self, leaves: &[T]) -> Result<T, MerkleError> {
+        let mut buffer: [(u32, T); N] = core::array::from_fn(|_| (0, T::default()));
+        self.try_root_with_buffer(leaves, &mut buffer)
+    }
+
+    pub fn root_with_capacity<const N: usize>(&self, leaves: &[T]) -> Option<T> {
+        self.try_root_with_capacity::<N>(leaves).ok()
+    }
+
+    pub fn verify_with_capacity<const N: usize>(&self, root: &T, leaves: &[T]) -> bool {
+        match self.root_with_capacity::<N>(leaves) {
+            Some(r) => &r == root,
+            _ => false,
+        }
+    }
+}
+
+/// Calculates the root from pairs of node index and hashed leaf.
+///
+/// `queue` is used as a ring buffer: every step pops at least one node before
+/// pushing the parent, so the queue never grows beyond the number of leaves.
+pub(crate) fn calculate_root<T, M>(queue: &mut [(u32, T)], lemmas: &[T]) -> Result<T, MerkleError>
+where
+    T: Default + Clone,
+    M: Merge<Item = T>,
+{
+    visit_nodes::<T, M, _>(queue, lemmas, |_, _| {})
+}
+
+/// Same as `calculate_root`, but `visit` is called with every node merged on the
+/// way to the root, including the leaves, the lemmas and the root itself.
+pub(crate) fn visit_nodes<T, M, F>(
+    queue: &mut [(u32, T)],
+    lemmas: &[T],
+    mut visit: F,
+) -> Result<T, MerkleError>
+where
+    T: Default + Clone,
+    M: Merge<Item = T>,
+    F: FnMut(u32, &T),
+{
+    queue.sort_unstable_by_key(|i| Reverse(i.0));
+    if let Some(pair) = queue.windows(2).find(|pair| pair[0].0 == pair[1].0) {
+        return Err(MerkleError::DuplicateNodeIndex { index: pair[0].0 });
+    }
+
+    let capacity = queue.len();
+    let mut head = 0;
+    let mut len = capacity;
+    let mut lemmas_iter = lemmas.iter();
+
+    while len > 0 {
+        let (index, node) = mem::take(&mut queue[head]);
+        head = (head + 1) % capacity;
+        len -= 1;
+        visit(index, &node);
+
+        if index == 0 {
+            // ensure that all lemmas and leaves are consumed
+            let unconsumed_lemmas = lemmas_iter.len();
+            if unconsumed_lemmas > 0 {
+                return Err(MerkleError::UnconsumedLemmas {
+                    count: unconsumed_lemmas,
+                });
+            }
+            if len > 0 {
+                return Err(MerkleError::UnconsumedNodes { count: len });
+            }
+            return Ok(node);
+        }
+
+        let sibling = if len > 0 && queue[head].0 == index.sibling() {
+            let (_, sibling) = mem::take(&mut queue[head]);
+            head = (head + 1) % capacity;
+            len -= 1;
+            sibling
+        } else {
+            lemmas_iter
+                .next()
+                .cloned()
+                .ok_or(MerkleError::LemmaCountMismatch)?
+        };
+        visit(index.sibling(), &sibling);
+
+        let parent_node = if index.is_left() {
+            M::merge(&node, &sibling)
+        } else {
+            M::merge(&sibling, &node)
+        };
+
+        queue[(head + len) % capacity] = (index.parent(), parent_node);
+        len += 1;
+    }
+
+    unreachable!("the queue is not empty until the root is reached")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{leaves_and_indices, MergeI32, CBMTI32};
+    use proptest::proptest;
+
+    type CBMTI32ProofRef<'a> = MerkleProofRef<'a, i32, MergeI32>;
+
+    #[test]
+    fn verify_without_allocation() {
+        let leaves = vec![2i32, 3, 5, 7, 11, 13];
+        let root = CBMTI32::build_merkle_root(&leaves);
+
+        let indices = [5, 10];
+        let lemmas = [11, 3, 2];
+        let proof = CBMTI32ProofRef::new(&indices, &lemmas);
+        assert_eq!(Ok(1), proof.try_root_with_capacity::<2>(&[2, 13]));
+        assert!(proof.verify_with_capacity::<4>(&root, &[13, 2]));
+
+        let mut buffer = [(7, 7); 3];
+        assert!(proof.verify_with_buffer(&root, &[2, 13], &mut buffer));
+
+        assert_eq!(
+            Err(MerkleError::BufferTooSmall { required: 2 }),
+            proof.try_root_with_capacity::<1>(&[2, 13])
+        );
+        assert_eq!(
+            Err(MerkleError::LemmaCountMismatch),
+            CBMTI32ProofRef::new(&indices, &lemmas[..2]).try_root_with_capacity::<2>(&[2, 13])
+        );
+        assert_eq!(
+            Err(MerkleError::EmptyProof),
+            CBMTI32ProofRef::new(&[], &[]).try_root_with_capacity::<2>(&[])
+        );
+    }
+
+    fn _proof_ref_root_is_same_as_proof_root(leaves: Vec<i32>, leaf_indices: Vec<u32>) {
+        let proof_leaves = leaf_indices
+            .iter()
+            .map(|i| leaves[*i as usize])
+            .collect::<Vec<_>>();
+
+        let proof = CBMTI32::build_merkle_proof(&leaves, &leaf_indices).unwrap();
+        let mut buffer = vec![(0, 0); proof_leaves.len()];
+        assert_eq!(
+            proof.try_root(&proof_leaves),
+            proof
+                .as_proof_ref()
+                .try_root_with_buffer(&proof_leaves, &mut buffer)
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn proof_ref_root_is_same_as_proof_root(input in leaves_and_indices(2..1000)) {
+            _proof_ref_root_is_same_as_proof_root(input.0, input.1);
+        }
+    }
+}
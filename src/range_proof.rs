@@ -0,0 +1,395 @@
+use crate::error::MerkleError;
+use crate::merkle_tree::{leaf_index_to_node, Merge, MerkleTree};
+use crate::vec::Vec;
+use core::marker::PhantomData;
+use core::ops::Range;
+
+/// A proof of a contiguous range of leaves.
+///
+/// The leaves of a range are contiguous nodes, so their indices are derived from
+/// the leaf count and the start of the range instead of being carried in the
+/// proof. The lemmas are the same as those of `MerkleTree::build_proof` for the
+/// leaves of the range.
+pub struct RangeProof<T, M> {
+    leaves_count: u32,
+    lemmas: Vec<T>,
+    merge: PhantomData<M>,
+}
+
+impl<T, M> MerkleTree<T, M>
+where
+    T: Ord + Default + Clone,
+    M: Merge<Item = T>,
+{
+    /// `range`: the range of leaf indices
+    pub fn build_range_proof(&self, range: Range<u32>) -> Option<RangeProof<T, M>> {
+        self.try_build_range_proof(range).ok()
+    }
+
+    /// Same as `build_range_proof`, but returns the reason of the failure.
+    pub fn try_build_range_proof(
+        &self,
+        range: Range<u32>,
+    ) -> Result<RangeProof<T, M>, MerkleError> {
+        let leaves_count = self.leaves_count();
+        if leaves_count == 0 {
+            return Err(MerkleError::EmptyTree);
+        }
+        if range.start >= range.end {
+            return Err(MerkleError::EmptyIndices);
+        }
+        if range.end > leaves_count {
+            return Err(MerkleError::IndexOutOfRange {
+                index: range.start.max(leaves_count),
+            });
+        }
+
+        let leaf_indices = range.collect::<Vec<_>>();
+        let proof = self.try_build_proof(&leaf_indices)?;
+        Ok(RangeProof {
+            leaves_count,
+            lemmas: proof.lemmas,
+            merge: PhantomData,
+        })
+    }
+}
+
+impl<T, M> RangeProof<T, M>
+where
+    T: Ord + Default + Clone,
+    M: Merge<Item = T>,
+{
+    pub fn new(leaves_count: u32, lemmas: Vec<T>) -> Self {
+        RangeProof {
+            leaves_count,
+            lemmas,
+            merge: PhantomData,
+        }
+    }
+
+    /// `start`: the index of the first leaf
+    /// `leaves`: the leaves of the range in order
+    /// `leaves_count`: the number of leaves of the tree, from a trusted source,
+    /// since the root doesn't commit to it
+    pub fn root(&self, start: u32, leaves: &[T], leaves_count: u32) -> Option<T> {
+        self.try_root(start, leaves, leaves_count).ok()
+    }
+
+    /// Same as `root`, but returns the reason of the failure.
+    ///
+    /// The root is calculated in a single pass from the root down to the leaves,
+    /// each leaf is hashed when it's merged, holding `O(log n)` nodes.
+    pub fn try_root(&self, start: u32, leaves: &[T], leaves_count: u32) -> Result<T, MerkleError> {
+        if self.leaves_count != leaves_count {
+            return Err(MerkleError::TreeSizeMismatch {
+                expected: leaves_count,
+                actual: self.leaves_count,
+            });
+        }
+        if leaves.is_empty() {
+            return Err(MerkleError::EmptyProof);
+        }
+        let out_of_range = MerkleError::IndexOutOfRange {
+            index: start.saturating_add((leaves.len() - 1).min(u32::MAX as usize) as u32),
+        };
+        let last = Some(leaves.len() - 1)
+            .filter(|len| *len < leaves_count as usize)
+            .and_then(|len| start.checked_add(len as u32))
+            .ok_or(out_of_range)?;
+        let first_node = leaf_index_to_node(leaves_count, start).ok_or(out_of_range)?;
+        let last_node = leaf_index_to_node(leaves_count, last).ok_or(out_of_range)?;
+
+        let nodes = RangeNodes::new(first_node, last_node);
+        let lemmas_count = nodes.lemmas().len();
+        if self.lemmas.len() < lemmas_count {
+            return Err(MerkleError::LemmaCountMismatch);
+        }
+        if self.lemmas.len() > lemmas_count {
+            return Err(MerkleError::UnconsumedLemmas {
+                count: self.lemmas.len() - lemmas_count,
+            });
+        }
+
+        Ok(nodes.calculate::<T, M>(0, leaves, &self.lemmas))
+    }
+
+    pub fn verify(&self, root: &T, start: u32, leaves: &[T], leaves_count: u32) -> bool {
+        match self.root(start, leaves, leaves_count) {
+            Some(r) => &r == root,
+            _ => false,
+        }
+    }
+
+    pub fn leaves_count(&self) -> u32 {
+        self.leaves_count
+    }
+
+    pub fn lemmas(&self) -> &[T] {
+        &self.lemmas
+    }
+}
+
+// Node indices are `u32`, so a node is at most at depth 31, and there are at most
+// 4 lemmas at each depth except the root's.
+const LEMMAS_CAPACITY: usize = 32 * 4;
+
+fn depth(index: u64) -> u32 {
+    63 - (index + 1).leading_zeros()
+}
+
+/// The nodes merged on the way from a range of leaves to the root.
+///
+/// The leaves of a range are contiguous nodes at up to two depths, and the
+/// ancestors of each part at every depth are contiguous too, so only the siblings
+/// at both ends of these intervals can be lemmas.
+struct RangeNodes {
+    // inclusive intervals of the leaves, one per depth
+    leaves: [(u64, u64); 2],
+    leaves_len: usize,
+    // the node indices of the lemmas in descending order, which is the order
+    // `calculate_root` takes them
+    lemmas: [u64; LEMMAS_CAPACITY],
+    lemmas_len: usize,
+}
+
+impl RangeNodes {
+    fn new(first: u32, last: u32) -> Self {
+        let (first, last) = (u64::from(first), u64::from(last));
+        // the first node at the depth below `first`
+        let next_depth = (2 << depth(first)) - 1;
+        let mut nodes = RangeNodes {
+            leaves: [(first, last), (0, 0)],
+            leaves_len: 1,
+            lemmas: [0; LEMMAS_CAPACITY],
+            lemmas_len: 0,
+        };
+        if last >= next_depth {
+            nodes.leaves = [(first, next_depth - 1), (next_depth, last)];
+            nodes.leaves_len = 2;
+        }
+
+        for d in (1..=depth(last)).rev() {
+            let mut candidates = [u64::MAX; 4];
+            for (i, (left, right)) in nodes.ancestors(d).enumerate() {
+                if left & 1 == 0 {
+                    candidates[i * 2] = left - 1;
+                }
+                if right & 1 == 1 {
+                    candidates[i * 2 + 1] = right + 1;
+                }
+            }
+            candidates.sort_unstable_by(|a, b| b.cmp(a));
+            for (i, candidate) in candidates.iter().enumerate() {
+                if *candidate != u64::MAX
+                    && (i == 0 || candidates[i - 1] != *candidate)
+                    && !nodes.contains(*candidate)
+                {
+                    nodes.lemmas[nodes.lemmas_len] = *candidate;
+                    nodes.lemmas_len += 1;
+                }
+            }
+        }
+        nodes
+    }
+
+    /// The intervals of the ancestors at depth `d` of the leaves.
+    fn ancestors(&self, d: u32) -> impl Iterator<Item = (u64, u64)> + '_ {
+        self.leaves[..self.leaves_len]
+            .iter()
+            .filter(move |(first, _)| depth(*first) >= d)
+            .map(move |(first, last)| {
+                let shift = depth(*first) - d;
+                (((first + 1) >> shift) - 1, ((last + 1) >> shift) - 1)
+            })
+    }
+
+    /// Whether the node is one of the leaves or their ancestors.
+    fn contains(&self, index: u64) -> bool {
+        self.ancestors(depth(index))
+            .any(|(first, last)| first <= index && index <= last)
+    }
+
+    fn lemmas(&self) -> &[u64] {
+        &self.lemmas[..self.lemmas_len]
+    }
+
+    /// Calculates the node at `index`, which is either one of the leaves or their
+    /// ancestors, or a lemma. The lemmas must be as many as `lemmas()`.
+    fn calculate<T, M>(&self, index: u64, leaves: &[T], lemmas: &[T]) -> T
+    where
+        M: Merge<Item = T>,
+        T: Clone,
+    {
+        let (first, last) = (self.leaves[0].0, self.leaves[self.leaves_len - 1].1);
+        if first <= index && index <= last {
+            M::hash_leaf(&leaves[(index - first) as usize])
+        } else if self.contains(index) {
+            let left = self.calculate::<T, M>((index << 1) + 1, leaves, lemmas);
+            let right = self.calculate::<T, M>((index << 1) + 2, leaves, lemmas);
+            M::merge(&left, &right)
+        } else {
+            let position = self
+                .lemmas()
+                .iter()
+                .position(|lemma| *lemma == index)
+                .expect("siblings of the merged nodes are lemmas");
+            lemmas[position].clone()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{MergeI32, CBMTI32};
+    use proptest::collection::vec;
+    use proptest::num::i32;
+    use proptest::prelude::*;
+    use proptest::proptest;
+
+    #[test]
+    fn range_proof() {
+        let leaves = vec![2i32, 3, 5, 7, 11, 13, 17];
+        let tree = CBMTI32::build_merkle_tree(&leaves);
+        let root = tree.root();
+
+        let proof = tree.build_range_proof(2..5).unwrap();
+        assert_eq!(7, proof.leaves_count());
+        assert_eq!(
+            tree.build_proof(&[2, 3, 4]).unwrap().lemmas(),
+            proof.lemmas()
+        );
+        assert!(proof.verify(&root, 2, &[5, 7, 11], 7));
+        assert!(!proof.verify(&root, 1, &[3, 5, 7], 7));
+        assert!(!proof.verify(&root, 2, &[5, 7], 7));
+        assert!(!proof.verify(&root, 2, &[5, 7, 13], 7));
+
+        let proof = tree.build_range_proof(0..7).unwrap();
+        assert!(proof.lemmas().is_empty());
+        assert!(proof.verify(&root, 0, &leaves, 7));
+    }
+
+    #[test]
+    fn range_proof_errors() {
+        let tree = CBMTI32::build_merkle_tree(&[2, 3, 5, 7, 11]);
+        assert_eq!(
+            Some(MerkleError::EmptyIndices),
+            tree.try_build_range_proof(2..2).err()
+        );
+        assert_eq!(
+            Some(MerkleError::IndexOutOfRange { index: 5 }),
+            tree.try_build_range_proof(3..6).err()
+        );
+        assert_eq!(
+            Some(MerkleError::IndexOutOfRange { index: 5 }),
+            tree.try_build_range_proof(0..u32::MAX).err()
+        );
+        assert_eq!(
+            Some(MerkleError::IndexOutOfRange { index: 7 }),
+            tree.try_build_range_proof(7..9).err()
+        );
+        assert_eq!(
+            Some(MerkleError::EmptyTree),
+            CBMTI32::build_merkle_tree(&[])
+                .try_build_range_proof(0..1)
+                .err()
+        );
+
+        let proof = tree.build_range_proof(3..5).unwrap();
+        assert_eq!(
+            Some(MerkleError::EmptyProof),
+            proof.try_root(3, &[], 5).err()
+        );
+        assert_eq!(
+            Some(MerkleError::IndexOutOfRange { index: 5 }),
+            proof.try_root(4, &[7, 11], 5).err()
+        );
+        assert_eq!(
+            Some(MerkleError::IndexOutOfRange { index: u32::MAX }),
+            proof.try_root(u32::MAX, &[7, 11], 5).err()
+        );
+        assert_eq!(
+            Some(MerkleError::TreeSizeMismatch {
+                expected: 6,
+                actual: 5
+            }),
+            proof.try_root(3, &[7, 11], 6).err()
+        );
+
+        let lemmas = proof.lemmas().to_vec();
+        assert_eq!(
+            Some(MerkleError::LemmaCountMismatch),
+            RangeProof::<i32, MergeI32>::new(5, lemmas[1..].to_vec())
+                .try_root(3, &[7, 11], 5)
+                .err()
+        );
+        let mut more_lemmas = lemmas;
+        more_lemmas.push(0);
+        assert_eq!(
+            Some(MerkleError::UnconsumedLemmas { count: 1 }),
+            RangeProof::<i32, MergeI32>::new(5, more_lemmas)
+                .try_root(3, &[7, 11], 5)
+                .err()
+        );
+
+        // the last leaf of the largest tree is at node `u32::MAX - 1`, 31 levels deep
+        let proof = RangeProof::<i32, MergeI32>::new(1 << 31, vec![0; 31]);
+        assert!(proof.try_root(u32::MAX >> 1, &[2], 1 << 31).is_ok());
+
+        let proof = RangeProof::<i32, MergeI32>::new(u32::MAX, vec![]);
+        assert_eq!(
+            Some(MerkleError::IndexOutOfRange {
+                index: u32::MAX - 1
+            }),
+            proof.try_root(u32::MAX - 1, &[2], u32::MAX).err()
+        );
+    }
+
+    #[test]
+    fn forged_range_proof() {
+        let leaves = vec![2i32, 3, 5, 7, 11];
+        let tree = CBMTI32::build_merkle_tree(&leaves);
+        let root = tree.root();
+
+        // leaf 4 of 5 is at the same node as leaf 0 of 9
+        let lemmas = tree.build_range_proof(4..5).unwrap().lemmas().to_vec();
+        let proof = RangeProof::<i32, MergeI32>::new(9, lemmas);
+        assert!(!proof.verify(&root, 0, &[11], 5));
+        assert_eq!(
+            Some(MerkleError::TreeSizeMismatch {
+                expected: 5,
+                actual: 9
+            }),
+            proof.try_root(0, &[11], 5).err()
+        );
+
+        // the interior node 1 is at the same node as leaf 0 of 2
+        let proof = RangeProof::<i32, MergeI32>::new(2, vec![tree.nodes()[2]]);
+        assert!(!proof.verify(&root, 0, &[tree.nodes()[1]], 5));
+    }
+
+    fn _range_proof_root_is_same_as_tree_root(leaves: Vec<i32>, start: u32, end: u32) {
+        let tree = CBMTI32::build_merkle_tree(&leaves);
+        let proof = tree.build_range_proof(start..end).unwrap();
+        assert_eq!(
+            Some(tree.root()),
+            proof.root(
+                start,
+                &leaves[start as usize..end as usize],
+                leaves.len() as u32
+            )
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn range_proof_root_is_same_as_tree_root(input in vec(i32::ANY,  1..1000)
+            .prop_flat_map(|leaves| {
+                let len = leaves.len() as u32;
+                (Just(leaves), 0..len).prop_flat_map(move |(leaves, start)| (Just(leaves), Just(start), start + 1..=len))
+            })
+        ) {
+            _range_proof_root_is_same_as_tree_root(input.0, input.1, input.2);
+        }
+    }
+}
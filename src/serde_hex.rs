@@ -0,0 +1,214 @@
+//! Helpers to serialize byte array hashes as `0x` prefixed hex strings, for use
+//! with `#[serde(with = "...")]`.
+//!
+//! ```ignore
+//! #[derive(Serialize, Deserialize)]
+//! struct TransactionProof {
+//!     #[serde(with = "merkle_cbt::serde_hex")]
+//!     root: [u8; 32],
+//!     #[serde(with = "merkle_cbt::serde_hex::proof")]
+//!     proof: MerkleProof<[u8; 32], Blake2bMerge>,
+//! }
+//! ```
+
+use crate::vec::Vec;
+use core::fmt;
+use serde::de::{Error, Visitor};
+use serde::{Deserializer, Serializer};
+
+cfg_if::cfg_if! {
+    if #[cfg(feature = "std")] {
+        use std::string::String;
+    } else {
+        use alloc::string::String;
+    }
+}
+
+const HEX_CHARS: &[u8; 16] = b"0123456789abcdef";
+
+/// Encodes `bytes` as a `0x` prefixed lowercase hex string.
+pub fn to_hex(bytes: &[u8]) -> String {
+    let mut hex = String::with_capacity(2 + bytes.len() * 2);
+    hex.push_str("0x");
+    for byte in bytes {
+        hex.push(HEX_CHARS[(byte >> 4) as usize] as char);
+        hex.push(HEX_CHARS[(byte & 0xf) as usize] as char);
+    }
+    hex
+}
+
+fn hex_value(c: u8) -> Option<u8> {
+    match c {
+        b'0'..=b'9' => Some(c - b'0'),
+        b'a'..=b'f' => Some(c - b'a' + 10),
+        b'A'..=b'F' => Some(c - b'A' + 10),
+        _ => None,
+    }
+}
+
+/// Decodes a `0x` prefixed hex string of exactly `N` bytes.
+pub fn from_hex<const N: usize>(hex: &str) -> Option<[u8; N]> {
+    let hex = hex.strip_prefix("0x")?.as_bytes();
+    if hex.len() != N * 2 {
+        return None;
+    }
+    let mut result = [0u8; N];
+    for (byte, pair) in result.iter_mut().zip(hex.chunks_exact(2)) {
+        *byte = (hex_value(pair[0])? << 4) | hex_value(pair[1])?;
+    }
+    Some(result)
+}
+
+struct HexVisitor<const N: usize>;
+
+impl<'de, const N: usize> Visitor<'de> for HexVisitor<N> {
+    type Value = [u8; N];
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        write!(formatter, "a 0x prefixed hex string of {} bytes", N)
+    }
+
+    fn visit_str<E: Error>(self, value: &str) -> Result<Self::Value, E> {
+        from_hex(value).ok_or_else(|| E::invalid_value(serde::de::Unexpected::Str(value), &self))
+    }
+}
+
+pub fn serialize<S: Serializer, const N: usize>(
+    bytes: &[u8; N],
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&to_hex(bytes))
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>, const N: usize>(
+    deserializer: D,
+) -> Result<[u8; N], D::Error> {
+    deserializer.deserialize_str(HexVisitor)
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(transparent)]
+struct Hex<const N: usize>(#[serde(with = "self")] [u8; N]);
+
+/// Serializes a `Vec<[u8; N]>` as an array of hex strings.
+pub mod vec {
+    use super::{Hex, Vec};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer, const N: usize>(
+        items: &[[u8; N]],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(items.iter().map(|item| Hex(*item)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>, const N: usize>(
+        deserializer: D,
+    ) -> Result<Vec<[u8; N]>, D::Error> {
+        let items: Vec<Hex<N>> = Vec::deserialize(deserializer)?;
+        Ok(items.into_iter().map(|item| item.0).collect())
+    }
+}
+
+/// Serializes a `MerkleProof<[u8; N], M>` with lemmas as hex strings.
+pub mod proof {
+    use super::Vec;
+    use crate::merkle_tree::MerkleProof;
+    use core::marker::PhantomData;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Serialize)]
+    #[serde(rename = "MerkleProof")]
+    struct ProofRef<'a, const N: usize> {
+        indices: &'a [u32],
+        #[serde(with = "super::vec")]
+        lemmas: &'a [[u8; N]],
+    }
+
+    #[derive(Deserialize)]
+    #[serde(rename = "MerkleProof")]
+    struct ProofOwned<const N: usize> {
+        indices: Vec<u32>,
+        #[serde(with = "super::vec")]
+        lemmas: Vec<[u8; N]>,
+    }
+
+    pub fn serialize<S: Serializer, M, const N: usize>(
+        proof: &MerkleProof<[u8; N], M>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        ProofRef {
+            indices: &proof.indices,
+            lemmas: &proof.lemmas,
+        }
+        .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>, M, const N: usize>(
+        deserializer: D,
+    ) -> Result<MerkleProof<[u8; N], M>, D::Error> {
+        let proof = ProofOwned::deserialize(deserializer)?;
+        Ok(MerkleProof {
+            indices: proof.indices,
+            lemmas: proof.lemmas,
+            merge: PhantomData,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merkle_tree::MerkleProof;
+    use crate::tests::{CBMTBytes, MergeBytes};
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize)]
+    struct TransactionProof {
+        #[serde(with = "crate::serde_hex")]
+        root: [u8; 4],
+        #[serde(with = "crate::serde_hex::proof")]
+        proof: MerkleProof<[u8; 4], MergeBytes>,
+    }
+
+    #[test]
+    fn hex() {
+        assert_eq!("0x", to_hex(&[]));
+        assert_eq!("0x00ff10ab", to_hex(&[0, 255, 16, 171]));
+        assert_eq!(Some([0, 255, 16, 171]), from_hex("0x00ff10ab"));
+        assert_eq!(Some([0, 255, 16, 171]), from_hex("0x00FF10AB"));
+        assert_eq!(None, from_hex::<4>("00ff10ab"));
+        assert_eq!(None, from_hex::<4>("0x00ff10"));
+        assert_eq!(None, from_hex::<4>("0x00ff10abcd"));
+        assert_eq!(None, from_hex::<4>("0x00ff10ag"));
+    }
+
+    #[test]
+    fn proof_hex_json_round_trip() {
+        let leaves = vec![[1u8, 2, 3, 4], [5, 6, 7, 8], [9, 10, 11, 12]];
+        let root = CBMTBytes::build_merkle_root(&leaves);
+        let proof = TransactionProof {
+            root,
+            proof: CBMTBytes::build_merkle_proof(&leaves, &[1]).unwrap(),
+        };
+        let json = serde_json::to_string(&proof).unwrap();
+        assert_eq!(
+            format!(
+                r#"{{"root":"{}","proof":{{"indices":[3],"lemmas":["0x090a0b0c","0x01020304"]}}}}"#,
+                to_hex(&root)
+            ),
+            json
+        );
+
+        let decoded: TransactionProof = serde_json::from_str(&json).unwrap();
+        assert_eq!(root, decoded.root);
+        assert_eq!(proof.proof.indices(), decoded.proof.indices());
+        assert_eq!(proof.proof.lemmas(), decoded.proof.lemmas());
+        assert!(decoded.proof.verify(&decoded.root, &leaves[1..2]));
+
+        assert!(serde_json::from_str::<TransactionProof>(
+            r#"{"root":"0x0102","proof":{"indices":[],"lemmas":[]}}"#
+        )
+        .is_err());
+    }
+}
@@ -0,0 +1,112 @@
+//! serde implementations of `MerkleTree` and `MerkleProof`, the `PhantomData` of
+//! the merge is not serialized.
+
+use crate::merkle_tree::{MerkleProof, MerkleTree};
+use crate::vec::Vec;
+use core::marker::PhantomData;
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+#[derive(Serialize)]
+#[serde(rename = "MerkleProof")]
+struct ProofRef<'a, T> {
+    indices: &'a [u32],
+    lemmas: &'a [T],
+}
+
+#[derive(Deserialize)]
+#[serde(rename = "MerkleProof")]
+struct ProofOwned<T> {
+    indices: Vec<u32>,
+    lemmas: Vec<T>,
+}
+
+#[derive(Serialize)]
+#[serde(rename = "MerkleTree")]
+struct TreeRef<'a, T> {
+    nodes: &'a [T],
+}
+
+#[derive(Deserialize)]
+#[serde(rename = "MerkleTree")]
+struct TreeOwned<T> {
+    nodes: Vec<T>,
+}
+
+impl<T: Serialize, M> Serialize for MerkleProof<T, M> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        ProofRef {
+            indices: &self.indices,
+            lemmas: &self.lemmas,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de, T: Deserialize<'de>, M> Deserialize<'de> for MerkleProof<T, M> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let proof = ProofOwned::deserialize(deserializer)?;
+        Ok(MerkleProof {
+            indices: proof.indices,
+            lemmas: proof.lemmas,
+            merge: PhantomData,
+        })
+    }
+}
+
+impl<T: Serialize, M> Serialize for MerkleTree<T, M> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        TreeRef { nodes: &self.nodes }.serialize(serializer)
+    }
+}
+
+/// Only the shape of the tree is checked, a CBMT always has `2n - 1` nodes. The
+/// hashes of the nodes are trusted.
+impl<'de, T: Deserialize<'de>, M> Deserialize<'de> for MerkleTree<T, M> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let tree = TreeOwned::deserialize(deserializer)?;
+        if tree.nodes.len() & 1 == 0 && !tree.nodes.is_empty() {
+            return Err(D::Error::custom(
+                "the number of merkle tree nodes must be odd",
+            ));
+        }
+        Ok(MerkleTree {
+            nodes: tree.nodes,
+            merge: PhantomData,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::merkle_tree::{MerkleProof, MerkleTree};
+    use crate::tests::{MergeI32, CBMTI32};
+
+    #[test]
+    fn proof_json_round_trip() {
+        let leaves = vec![2i32, 3, 5, 7, 11, 13];
+        let proof = CBMTI32::build_merkle_proof(&leaves, &[0, 5]).unwrap();
+        let json = serde_json::to_string(&proof).unwrap();
+        assert_eq!(r#"{"indices":[5,10],"lemmas":[11,3,2]}"#, json);
+
+        let decoded: MerkleProof<i32, MergeI32> = serde_json::from_str(&json).unwrap();
+        assert_eq!(proof.indices(), decoded.indices());
+        assert_eq!(proof.lemmas(), decoded.lemmas());
+        assert!(decoded.verify(&CBMTI32::build_merkle_root(&leaves), &[2, 13]));
+    }
+
+    #[test]
+    fn tree_json_round_trip() {
+        let tree = CBMTI32::build_merkle_tree(&[2i32, 3, 5, 7, 11]);
+        let json = serde_json::to_string(&tree).unwrap();
+        assert_eq!(r#"{"nodes":[4,-2,2,4,2,3,5,7,11]}"#, json);
+
+        let decoded: MerkleTree<i32, MergeI32> = serde_json::from_str(&json).unwrap();
+        assert_eq!(tree.nodes(), decoded.nodes());
+
+        let empty: MerkleTree<i32, MergeI32> = serde_json::from_str(r#"{"nodes":[]}"#).unwrap();
+        assert!(empty.nodes().is_empty());
+
+        assert!(serde_json::from_str::<MerkleTree<i32, MergeI32>>(r#"{"nodes":[1,2]}"#).is_err());
+    }
+}
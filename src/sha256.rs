@@ -0,0 +1,197 @@
+//! SHA-256 based merges, enabled by the `sha256` feature.
+
+use crate::merkle_tree::{Merge, MixInLength};
+use crate::{length_to_h256, H256};
+use sha2::{Digest, Sha256};
+
+/// SHA-256 hash of `data`.
+pub fn sha256(data: &[u8]) -> H256 {
+    Sha256::digest(data).into()
+}
+
+/// Merges two nodes as `sha256(left || right)`.
+pub struct Sha256Merge;
+
+impl Merge for Sha256Merge {
+    type Item = H256;
+    fn merge(left: &Self::Item, right: &Self::Item) -> Self::Item {
+        let mut hasher = Sha256::new();
+        hasher.update(left);
+        hasher.update(right);
+        hasher.finalize().into()
+    }
+}
+
+impl MixInLength for Sha256Merge {
+    fn hash_length(leaves_count: u32) -> Self::Item {
+        length_to_h256(leaves_count)
+    }
+}
+
+/// Merges two nodes as `sha256(sha256(left || right))`, the merge used by Bitcoin
+/// merkle trees. Nodes are expected in internal byte order, which is the reverse
+/// of the hex strings displayed by block explorers.
+///
+/// Note that CBMT only matches Bitcoin's merkle root when the number of leaves is
+/// a power of two, because Bitcoin duplicates the last node of odd levels instead.
+pub struct DoubleSha256Merge;
+
+impl Merge for DoubleSha256Merge {
+    type Item = H256;
+    fn merge(left: &Self::Item, right: &Self::Item) -> Self::Item {
+        sha256(&Sha256Merge::merge(left, right))
+    }
+}
+
+impl MixInLength for DoubleSha256Merge {
+    fn hash_length(leaves_count: u32) -> Self::Item {
+        length_to_h256(leaves_count)
+    }
+}
+
+/// Domain separated SHA-256 merge in the style of RFC 6962: leaves are hashed as
+/// `sha256(0x00 || leaf)` and nodes as `sha256(0x01 || left || right)`, so an
+/// interior node can't be presented as a leaf in a proof.
+///
+/// Only the hashing follows RFC 6962, the tree shape is still CBMT.
+pub struct Rfc6962Merge;
+
+impl Rfc6962Merge {
+    pub const LEAF_PREFIX: u8 = 0x00;
+    pub const NODE_PREFIX: u8 = 0x01;
+}
+
+impl Merge for Rfc6962Merge {
+    type Item = H256;
+    fn merge(left: &Self::Item, right: &Self::Item) -> Self::Item {
+        let mut hasher = Sha256::new();
+        hasher.update([Self::NODE_PREFIX]);
+        hasher.update(left);
+        hasher.update(right);
+        hasher.finalize().into()
+    }
+
+    fn hash_leaf(leaf: &Self::Item) -> Self::Item {
+        let mut hasher = Sha256::new();
+        hasher.update([Self::LEAF_PREFIX]);
+        hasher.update(leaf);
+        hasher.finalize().into()
+    }
+}
+
+impl MixInLength for Rfc6962Merge {
+    fn hash_length(leaves_count: u32) -> Self::Item {
+        length_to_h256(leaves_count)
+    }
+}
+
+pub type CBMT = crate::CBMT<H256, Sha256Merge>;
+#[cfg(feature = "alloc")]
+pub type MerkleTree = crate::MerkleTree<H256, Sha256Merge>;
+#[cfg(feature = "alloc")]
+pub type MerkleProof = crate::MerkleProof<H256, Sha256Merge>;
+pub type DoubleSha256CBMT = crate::CBMT<H256, DoubleSha256Merge>;
+#[cfg(feature = "alloc")]
+pub type DoubleSha256MerkleTree = crate::MerkleTree<H256, DoubleSha256Merge>;
+#[cfg(feature = "alloc")]
+pub type DoubleSha256MerkleProof = crate::MerkleProof<H256, DoubleSha256Merge>;
+pub type Rfc6962CBMT = crate::CBMT<H256, Rfc6962Merge>;
+#[cfg(feature = "alloc")]
+pub type Rfc6962MerkleTree = crate::MerkleTree<H256, Rfc6962Merge>;
+#[cfg(feature = "alloc")]
+pub type Rfc6962MerkleProof = crate::MerkleProof<H256, Rfc6962Merge>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::h256;
+
+    fn reversed(mut hash: H256) -> H256 {
+        hash.reverse();
+        hash
+    }
+
+    #[test]
+    fn sha256_abc() {
+        assert_eq!(
+            h256("0xba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"),
+            sha256(b"abc")
+        );
+    }
+
+    #[test]
+    fn sha256_merge() {
+        let abc = sha256(b"abc");
+        assert_eq!(
+            h256("0x3b771ca97e3c17698aff21227fa046b5622a30d8ee5d2de4ee1111a1cdf258ee"),
+            Sha256Merge::merge(&abc, &abc)
+        );
+        assert_eq!(
+            sha256(&Sha256Merge::merge(&abc, &abc)),
+            DoubleSha256Merge::merge(&abc, &abc)
+        );
+    }
+
+    #[test]
+    fn sha256_mix_in_length() {
+        let root = CBMT::build_merkle_root(&[sha256(b"a"), sha256(b"b"), sha256(b"c")]);
+        let mut data = root.to_vec();
+        data.extend_from_slice(&[3u8]);
+        data.resize(64, 0);
+        assert_eq!(
+            sha256(&data),
+            CBMT::build_merkle_root_with_length(&[sha256(b"a"), sha256(b"b"), sha256(b"c")])
+        );
+    }
+
+    #[test]
+    fn rfc6962_merge() {
+        let abc = sha256(b"abc");
+        let mut leaf = [0u8; 33];
+        leaf[1..].copy_from_slice(&abc);
+        assert_eq!(sha256(&leaf), Rfc6962Merge::hash_leaf(&abc));
+
+        let mut node = [1u8; 65];
+        node[1..33].copy_from_slice(&abc);
+        node[33..].copy_from_slice(&abc);
+        assert_eq!(sha256(&node), Rfc6962Merge::merge(&abc, &abc));
+    }
+
+    #[test]
+    fn rfc6962_rejects_interior_node_as_leaf() {
+        let leaves = [b"a", b"b", b"c", b"d"]
+            .iter()
+            .map(|leaf| sha256(&leaf[..]))
+            .collect::<Vec<_>>();
+        let tree = Rfc6962CBMT::build_merkle_tree(&leaves);
+        let root = tree.root();
+
+        let proof = tree.build_proof(&[2]).unwrap();
+        assert!(proof.verify(&root, &leaves[2..3]));
+
+        let forged_proof =
+            crate::MerkleProof::<H256, Rfc6962Merge>::new(vec![1], vec![tree.nodes()[2]]);
+        assert!(!forged_proof.verify(&root, &[tree.nodes()[1]]));
+    }
+
+    #[test]
+    fn bitcoin_block_100000_merkle_root() {
+        // block 000000000003ba27aa200b1cecaad478d2b00432346c3f1f3986da1afd33e506
+        let tx_hashes = [
+            "0x8c14f0db3df150123e6f3dbbf30f8b955a8249b62ac1d1ff16284aefa3d06d87",
+            "0xfff2525b8931402dd09222c50775608f75787bd2b87e56995a7bdd30f79702c4",
+            "0x6359f0868171b1d194cbee1af2f16ea598ae8fad666d9b012c8ed2b79a236ec4",
+            "0xe9a66845e05d5abc0ad04ec80f774a7e585c6e8db975962d069a522137b80c1d",
+        ]
+        .iter()
+        .map(|hash| reversed(h256(hash)))
+        .collect::<Vec<_>>();
+        let root = reversed(h256(
+            "0xf3e94742aca4b5ef85488dc37c06c3282295ffec960994b2c0d5ac2a25a95766",
+        ));
+        assert_eq!(root, DoubleSha256CBMT::build_merkle_root(&tx_hashes));
+
+        let proof = DoubleSha256CBMT::build_merkle_proof(&tx_hashes, &[1, 2]).unwrap();
+        assert!(proof.verify(&root, &tx_hashes[1..3]));
+    }
+}
@@ -0,0 +1,266 @@
+use crate::error::MerkleError;
+use crate::merkle_tree::{leaf_index_to_node, Merge, MerkleProof, MerkleTree, CBMT};
+use crate::{vec, vec::Vec};
+use core::marker::PhantomData;
+
+/// A CBMT of leaves sorted in strictly ascending order, which can prove that a
+/// key is absent by proving its two adjacent leaves.
+pub struct SortedCbmt<T, M> {
+    leaves: Vec<T>,
+    tree: MerkleTree<T, M>,
+}
+
+impl<T, M> SortedCbmt<T, M>
+where
+    T: Ord + Default + Clone,
+    M: Merge<Item = T>,
+{
+    /// Returns `UnsortedLeaves` with the index of the first leaf that is not
+    /// greater than the previous one.
+    pub fn new(leaves: Vec<T>) -> Result<Self, MerkleError> {
+        if let Some(index) = leaves.windows(2).position(|pair| pair[0] >= pair[1]) {
+            return Err(MerkleError::UnsortedLeaves {
+                index: index as u32 + 1,
+            });
+        }
+        let tree = CBMT::<T, M>::build_merkle_tree(&leaves);
+        Ok(SortedCbmt { leaves, tree })
+    }
+
+    /// The index of the leaf equal to `key`.
+    pub fn position(&self, key: &T) -> Option<u32> {
+        self.leaves.binary_search(key).ok().map(|i| i as u32)
+    }
+
+    /// Returns `None` if `key` is in the tree.
+    pub fn build_absence_proof(&self, key: &T) -> Option<AbsenceProof<T, M>> {
+        let position = match self.leaves.binary_search(key) {
+            Ok(_) => return None,
+            Err(position) => position as u32,
+        };
+
+        let left = position
+            .checked_sub(1)
+            .map(|i| (i, self.leaves[i as usize].clone()));
+        let right = self
+            .leaves
+            .get(position as usize)
+            .map(|leaf| (position, leaf.clone()));
+        let leaf_indices = left
+            .iter()
+            .chain(right.iter())
+            .map(|(i, _)| *i)
+            .collect::<Vec<_>>();
+        let proof = if leaf_indices.is_empty() {
+            MerkleProof::new(vec![], vec![])
+        } else {
+            self.tree.build_proof(&leaf_indices)?
+        };
+
+        Some(AbsenceProof {
+            left,
+            right,
+            proof,
+            merge: PhantomData,
+        })
+    }
+
+    pub fn root(&self) -> T {
+        self.tree.root()
+    }
+
+    pub fn leaves(&self) -> &[T] {
+        &self.leaves
+    }
+
+    pub fn tree(&self) -> &MerkleTree<T, M> {
+        &self.tree
+    }
+}
+
+/// Proves that a key is absent from a `SortedCbmt`, by a multi-proof of the
+/// leaves right before and after it.
+///
+/// `left` is `None` if the key is less than all leaves, `right` is `None` if the
+/// key is greater than all leaves, and both are `None` for an empty tree.
+pub struct AbsenceProof<T, M> {
+    left: Option<(u32, T)>,
+    right: Option<(u32, T)>,
+    proof: MerkleProof<T, M>,
+    merge: PhantomData<M>,
+}
+
+impl<T, M> AbsenceProof<T, M>
+where
+    T: Ord + Default + Clone,
+    M: Merge<Item = T>,
+{
+    /// `left` and `right`: the positions and values of the adjacent leaves
+    pub fn new(left: Option<(u32, T)>, right: Option<(u32, T)>, proof: MerkleProof<T, M>) -> Self {
+        AbsenceProof {
+            left,
+            right,
+            proof,
+            merge: PhantomData,
+        }
+    }
+
+    /// Verifies that `key` is absent from the tree of `root`.
+    ///
+    /// `leaves_count` must come from a trusted source, the root doesn't commit to
+    /// it, and the first or last leaf can't be told apart from others without it.
+    pub fn verify(&self, root: &T, leaves_count: u32, key: &T) -> bool {
+        let adjacent = match (&self.left, &self.right) {
+            (None, None) => {
+                return leaves_count == 0
+                    && self.proof.indices().is_empty()
+                    && root == &T::default()
+            }
+            (Some((left, _)), None) => leaves_count > 0 && *left == leaves_count - 1,
+            (None, Some((right, _))) => leaves_count > 0 && *right == 0,
+            (Some((left, _)), Some((right, _))) => {
+                *right < leaves_count && left.checked_add(1) == Some(*right)
+            }
+        };
+        if !adjacent {
+            return false;
+        }
+        if matches!(&self.left, Some((_, leaf)) if leaf >= key)
+            || matches!(&self.right, Some((_, leaf)) if leaf <= key)
+        {
+            return false;
+        }
+
+        let indexed_leaves = self
+            .left
+            .iter()
+            .chain(self.right.iter())
+            .map(|(position, leaf)| {
+                leaf_index_to_node(leaves_count, *position).map(|index| (index, leaf.clone()))
+            })
+            .collect::<Option<Vec<_>>>();
+        match indexed_leaves {
+            Some(indexed_leaves) => self.proof.verify_with_indexed_leaves(root, &indexed_leaves),
+            None => false,
+        }
+    }
+
+    pub fn left(&self) -> Option<&(u32, T)> {
+        self.left.as_ref()
+    }
+
+    pub fn right(&self) -> Option<&(u32, T)> {
+        self.right.as_ref()
+    }
+
+    pub fn proof(&self) -> &MerkleProof<T, M> {
+        &self.proof
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::MergeI32;
+    use proptest::collection::btree_set;
+    use proptest::num::i32;
+    use proptest::proptest;
+
+    type SortedCbmtI32 = SortedCbmt<i32, MergeI32>;
+
+    #[test]
+    fn unsorted_leaves() {
+        assert_eq!(
+            Some(MerkleError::UnsortedLeaves { index: 3 }),
+            SortedCbmtI32::new(vec![1, 3, 5, 4]).err()
+        );
+        assert_eq!(
+            Some(MerkleError::UnsortedLeaves { index: 1 }),
+            SortedCbmtI32::new(vec![1, 1]).err()
+        );
+    }
+
+    #[test]
+    fn absence_proof() {
+        let tree = SortedCbmtI32::new(vec![2, 3, 5, 7, 11]).unwrap();
+        let root = tree.root();
+        assert!(tree.build_absence_proof(&5).is_none());
+
+        let proof = tree.build_absence_proof(&6).unwrap();
+        assert_eq!(Some(&(2, 5)), proof.left());
+        assert_eq!(Some(&(3, 7)), proof.right());
+        assert!(proof.verify(&root, 5, &6));
+        assert!(!proof.verify(&root, 5, &5));
+        assert!(!proof.verify(&root, 5, &8));
+        assert!(!proof.verify(&root, 6, &6));
+
+        let proof = tree.build_absence_proof(&1).unwrap();
+        assert_eq!(None, proof.left());
+        assert_eq!(Some(&(0, 2)), proof.right());
+        assert!(proof.verify(&root, 5, &1));
+
+        let proof = tree.build_absence_proof(&12).unwrap();
+        assert_eq!(Some(&(4, 11)), proof.left());
+        assert_eq!(None, proof.right());
+        assert!(proof.verify(&root, 5, &12));
+        assert!(!proof.verify(&root, 5, &11));
+    }
+
+    #[test]
+    fn absence_proof_empty_tree() {
+        let tree = SortedCbmtI32::new(vec![]).unwrap();
+        let proof = tree.build_absence_proof(&1).unwrap();
+        assert!(proof.verify(&tree.root(), 0, &1));
+        assert!(!proof.verify(&tree.root(), 1, &1));
+    }
+
+    #[test]
+    fn absence_proof_rejects_non_adjacent_leaves() {
+        let tree = SortedCbmtI32::new(vec![2, 3, 5, 7, 11]).unwrap();
+        let root = tree.root();
+
+        // 3 and 7 are both in the tree and 6 is between them, but 5 is skipped
+        let proof = AbsenceProof::new(
+            Some((1, 3)),
+            Some((3, 7)),
+            tree.tree().build_proof(&[1, 3]).unwrap(),
+        );
+        assert!(!proof.verify(&root, 5, &6));
+
+        // the last leaf is claimed to be 7 in a tree of 4 leaves
+        let proof = AbsenceProof::new(Some((3, 7)), None, tree.tree().build_proof(&[3]).unwrap());
+        assert!(!proof.verify(&root, 5, &8));
+        assert!(!proof.verify(&root, 4, &8));
+
+        // the last leaf of `u32::MAX` leaves doesn't fit in a node index
+        let proof = AbsenceProof::<i32, MergeI32>::new(
+            Some((u32::MAX - 1, 1)),
+            None,
+            MerkleProof::new(vec![], vec![]),
+        );
+        assert!(!proof.verify(&0, u32::MAX, &2));
+    }
+
+    fn _absence_proof(leaves: Vec<i32>, key: i32) {
+        let tree = SortedCbmtI32::new(leaves.clone()).unwrap();
+        match tree.build_absence_proof(&key) {
+            Some(proof) => {
+                assert!(!leaves.contains(&key));
+                assert!(proof.verify(&tree.root(), leaves.len() as u32, &key));
+            }
+            None => assert!(leaves.contains(&key)),
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn absence_proof_random(leaves in btree_set(i32::ANY, 0..200), key in i32::ANY) {
+            _absence_proof(leaves.into_iter().collect(), key);
+        }
+
+        #[test]
+        fn absence_proof_between_leaves(leaves in btree_set(-1000i32..1000, 1..200), key in -1001i32..1001) {
+            _absence_proof(leaves.into_iter().collect(), key);
+        }
+    }
+}
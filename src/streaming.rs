@@ -0,0 +1,148 @@
+use crate::merkle_tree::{Merge, CBMT};
+
+// A stack holds at most one block per bit of the position, plus the one being pushed.
+const STACK_CAPACITY: usize = usize::BITS as usize + 1;
+
+/// Aligned blocks of consecutive nodes of a perfect tree, each block is
+/// represented by the root of its subtree.
+struct BlockStack<T> {
+    // (start, size) of each block
+    blocks: [(usize, usize); STACK_CAPACITY],
+    roots: [T; STACK_CAPACITY],
+    len: usize,
+}
+
+impl<T: Default + Clone> BlockStack<T> {
+    fn new() -> Self {
+        BlockStack {
+            blocks: [(0, 0); STACK_CAPACITY],
+            roots: core::array::from_fn(|_| T::default()),
+            len: 0,
+        }
+    }
+
+    /// Pushes a block following the last one, and merges the blocks which form
+    /// an aligned block of double size.
+    fn push<M: Merge<Item = T>>(&mut self, start: usize, size: usize, root: T) {
+        self.blocks[self.len] = (start, size);
+        self.roots[self.len] = root;
+        self.len += 1;
+
+        while self.len >= 2 {
+            let (left_start, left_size) = self.blocks[self.len - 2];
+            let (_, right_size) = self.blocks[self.len - 1];
+            if left_size != right_size || left_start % (left_size << 1) != 0 {
+                break;
+            }
+            self.len -= 1;
+            self.roots[self.len - 1] = M::merge(&self.roots[self.len - 1], &self.roots[self.len]);
+            self.blocks[self.len - 1] = (left_start, left_size << 1);
+        }
+    }
+}
+
+impl<T, M> CBMT<T, M>
+where
+    T: Ord + Default + Clone,
+    M: Merge<Item = T>,
+{
+    /// Calculates the same root as `build_merkle_root` from an iterator of `len`
+    /// leaves, holding only `O(log len)` nodes and without allocation.
+    ///
+    /// Returns `None` if the iterator doesn't yield exactly `len` leaves.
+    ///
+    /// With `p` the largest power of two not greater than `len`, the root is a
+    /// perfect tree over `p` nodes: the merges of the last `2(len - p)` leaves
+    /// in pairs, followed by the first `2p - len` leaves. Both parts are folded
+    /// into aligned blocks as they arrive, and combined at the end.
+    pub fn build_merkle_root_from_iter<I>(leaves: I, len: usize) -> Option<T>
+    where
+        I: IntoIterator<Item = T>,
+    {
+        let mut leaves = leaves.into_iter();
+        if len == 0 {
+            return match leaves.next() {
+                Some(_) => None,
+                None => Some(T::default()),
+            };
+        }
+
+        let p = 1 << (usize::BITS - 1 - len.leading_zeros());
+        let pairs = len - p;
+
+        // the first leaves are at positions `pairs..p`
+        let mut tail = BlockStack::new();
+        for position in pairs..p {
+            tail.push::<M>(position, 1, M::hash_leaf(&leaves.next()?));
+        }
+
+        // the pairs of the remaining leaves are at positions `0..pairs`
+        let mut head = BlockStack::new();
+        for position in 0..pairs {
+            let left = M::hash_leaf(&leaves.next()?);
+            let right = M::hash_leaf(&leaves.next()?);
+            head.push::<M>(position, 1, M::merge(&left, &right));
+        }
+
+        if leaves.next().is_some() {
+            return None;
+        }
+
+        for i in 0..tail.len {
+            let (start, size) = tail.blocks[i];
+            head.push::<M>(start, size, tail.roots[i].clone());
+        }
+        debug_assert_eq!(head.len, 1);
+        Some(head.roots[0].clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::tests::HashLeafCBMTI32;
+    use proptest::collection::vec;
+    use proptest::num::i32;
+    use proptest::proptest;
+
+    #[test]
+    fn build_root_from_iter() {
+        assert_eq!(
+            Some(0),
+            HashLeafCBMTI32::build_merkle_root_from_iter(vec![], 0)
+        );
+        assert_eq!(
+            Some(69),
+            HashLeafCBMTI32::build_merkle_root_from_iter(vec![2], 1)
+        );
+
+        let leaves = vec![2i32, 3, 5, 7, 11];
+        assert_eq!(
+            Some(HashLeafCBMTI32::build_merkle_root(&leaves)),
+            HashLeafCBMTI32::build_merkle_root_from_iter(leaves.iter().cloned(), 5)
+        );
+
+        assert_eq!(
+            None,
+            HashLeafCBMTI32::build_merkle_root_from_iter(leaves.iter().cloned(), 4)
+        );
+        assert_eq!(
+            None,
+            HashLeafCBMTI32::build_merkle_root_from_iter(leaves.iter().cloned(), 6)
+        );
+        assert_eq!(
+            None,
+            HashLeafCBMTI32::build_merkle_root_from_iter(vec![1], 0)
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn build_root_from_iter_is_same_as_build_root(leaves in vec(i32::ANY, 0..2000)) {
+            let len = leaves.len();
+            assert_eq!(
+                Some(HashLeafCBMTI32::build_merkle_root(&leaves)),
+                HashLeafCBMTI32::build_merkle_root_from_iter(leaves, len)
+            );
+        }
+    }
+}
@@ -1,14 +1,44 @@
 #![no_std]
 #![no_main]
 
+use core::hint::black_box;
 use core::panic::PanicInfo;
+use merkle_cbt::blake2b::Blake2bMerge;
+use merkle_cbt::keccak256::Keccak256Merge;
+use merkle_cbt::merkle_tree::Merge;
+use merkle_cbt::sha256::Sha256Merge;
+use merkle_cbt::{MerkleProofRef, CBMT, H256};
+
+// memcpy and friends
+#[link(name = "c")]
+extern "C" {}
 
 #[panic_handler]
 fn panic(_info: &PanicInfo) -> ! {
     loop {}
 }
 
+// the precompiled core references it even with `panic = "abort"`
+#[no_mangle]
+pub extern "C" fn rust_eh_personality() {}
+
+// links without a global allocator
 #[no_mangle]
 pub extern "C" fn _start() -> ! {
+    let leaves = black_box([[1u8; 32], [2u8; 32], [3u8; 32]]);
+    black_box(verify_proof::<Blake2bMerge>(&leaves));
+    black_box(verify_proof::<Sha256Merge>(&leaves));
+    black_box(verify_proof::<Keccak256Merge>(&leaves));
     loop {}
 }
+
+fn verify_proof<M: Merge<Item = H256>>(leaves: &[H256; 3]) -> bool {
+    let root = CBMT::<H256, M>::build_merkle_root_from_iter(leaves.iter().cloned(), leaves.len());
+
+    let indices = [3u32];
+    let lemmas = [leaves[2], leaves[0]];
+    let proof = MerkleProofRef::<H256, M>::new(&indices, &lemmas);
+    root.map_or(false, |root| {
+        proof.verify_with_capacity::<4>(&root, &leaves[1..2])
+    })
+}